@@ -0,0 +1,90 @@
+/// This module contains the filters implemented in Rust. They transform the document in the form
+/// of pandoc's JSON AST (as produced by `pandoc -t json`) before the output format is created.
+/// Library users can implement the `Filter` trait to add their own transformations (see
+/// `Options::filters`).
+use crate::OutputFormat;
+
+use serde_json::json;
+use serde_json::value::Value;
+
+/// A transformation of the document implemented in Rust. The document is given as pandoc's JSON
+/// AST, the `walk` function of this module helps to visit all elements. Filters are applied in the
+/// order they are given, before the pandoc filters of the `filters` header field.
+pub trait Filter: Send + Sync {
+    /// Name of the filter as shown in log and error messages.
+    fn name(&self) -> &str;
+
+    /// Transforms the document for the conversion into the given output format. An error aborts
+    /// the conversion, the message is shown to the user.
+    fn apply(&self, document: &mut Value, format: &OutputFormat) -> Result<(), String>;
+}
+
+/// Calls the given function for every element (block or inline) of the document or part of a
+/// document, parents before their children. Elements are JSON objects with a `t` field stating
+/// the type (ex.: `Str`) and an optional `c` field holding the content. Changes made to an
+/// element by the function are visited as well.
+pub fn walk<F: FnMut(&mut Value)>(value: &mut Value, f: &mut F) {
+    if value.get("t").map(|x| x.is_string()).unwrap_or(false) {
+        f(value);
+    }
+    match value {
+        Value::Array(x) => x.iter_mut().for_each(|y| walk(y, f)),
+        Value::Object(x) => x.values_mut().for_each(|y| walk(y, f)),
+        _ => {}
+    }
+}
+
+/// Implements the `break_description` header field. Turns the line breaks within the descriptions
+/// of definition lists into hard line breaks, this way every line of a description is kept on it's
+/// own line in the output (ex.: the dialogue of a stage play).
+pub struct BreakDescription;
+
+impl Filter for BreakDescription {
+    fn name(&self) -> &str {
+        "break_description"
+    }
+
+    fn apply(&self, document: &mut Value, _format: &OutputFormat) -> Result<(), String> {
+        walk(document, &mut |element| {
+            if element["t"] != "DefinitionList" {
+                return;
+            }
+            for item in element["c"].as_array_mut().into_iter().flatten() {
+                // An item consists of the term and the list of it's descriptions.
+                if let Some(descriptions) = item.get_mut(1) {
+                    walk(descriptions, &mut |x| {
+                        if x["t"] == "SoftBreak" {
+                            *x = json!({"t": "LineBreak"});
+                        }
+                    });
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BreakDescription, Filter};
+    use crate::OutputFormat;
+
+    use serde_json::json;
+
+    #[test]
+    fn break_description() {
+        let line = json!([{"t": "Str", "c": "a"}, {"t": "SoftBreak"}, {"t": "Str", "c": "b"}]);
+        let mut document = json!({
+            "blocks": [
+                {"t": "Para", "c": line.clone()},
+                {"t": "DefinitionList", "c": [[line.clone(), [[{"t": "Plain", "c": line}]]]]},
+            ]
+        });
+        BreakDescription
+            .apply(&mut document, &OutputFormat::Pdf)
+            .unwrap();
+        assert_eq!(document["blocks"][0]["c"][1]["t"], "SoftBreak");
+        assert_eq!(document["blocks"][1]["c"][0][0][1]["t"], "SoftBreak");
+        assert_eq!(document["blocks"][1]["c"][0][1][0][0]["c"][1]["t"], "LineBreak");
+    }
+}
@@ -0,0 +1,223 @@
+/// This module handles the conversion of multiple documents at once.
+use crate::error::SmoothError;
+use crate::file::File;
+use crate::project::Project;
+use crate::util;
+use crate::{Options, OutputFormat};
+
+use std::collections::VecDeque;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use glob::glob;
+
+/// File extensions which are treated as markdown documents when a folder is given as input.
+const MARKDOWN_EXTENSIONS: [&str; 2] = ["md", "markdown"];
+
+/// Result of a batch conversion.
+pub struct Report {
+    /// Paths of all documents which were converted successfully.
+    pub succeeded: Vec<String>,
+    /// Paths of all documents which couldn't be converted accompanied by the error message.
+    pub failed: Vec<(String, String)>,
+}
+
+/// A single conversion within a batch.
+struct Job {
+    /// Path to the markdown file.
+    input: String,
+    /// Optional output path.
+    output: Option<String>,
+    /// Desired formats of the output file.
+    formats: Vec<OutputFormat>,
+}
+
+/// Converts a number of documents using multiple worker threads.
+pub struct Batch {
+    /// The conversions to be done.
+    jobs: Vec<Job>,
+    /// Maximal number of conversions running at the same time.
+    workers: usize,
+    /// Additional settings applied to all conversions.
+    options: Options,
+}
+
+impl<'a> Batch {
+    /// Returns a new batch for the given inputs. An input can either be a path to a markdown
+    /// file, a folder (all markdown files within the folder and it's sub folders will be
+    /// converted) or a glob pattern (ex.: `docs/**/*.md`).
+    pub fn new(
+        inputs: Vec<&'a str>,
+        workers: usize,
+        formats: Vec<OutputFormat>,
+        options: Options,
+    ) -> Result<Self, SmoothError<'a>> {
+        let mut files: Vec<String> = Vec::new();
+        let mut seen = Vec::new();
+        for input in inputs {
+            for file in Batch::expand_input(input)? {
+                let normalized = util::normalize_path(file.as_str(), None)?;
+                if !seen.contains(&normalized) {
+                    seen.push(normalized);
+                    files.push(file);
+                }
+            }
+        }
+        Ok(Self {
+            jobs: files
+                .into_iter()
+                .map(|x| Job {
+                    input: x,
+                    output: None,
+                    formats: formats.clone(),
+                })
+                .collect(),
+            workers: workers.max(1),
+            options,
+        })
+    }
+
+    /// Returns a new batch containing all documents of the given project. The default header
+    /// values of the project are applied to every document.
+    pub fn from_project(project: Project, workers: usize, options: Options) -> Self {
+        let options = Options {
+            defaults: project.defaults,
+            ..options
+        };
+        Self {
+            jobs: project
+                .documents
+                .into_iter()
+                .map(|x| Job {
+                    input: x.input,
+                    output: x.output,
+                    formats: x.formats,
+                })
+                .collect(),
+            workers: workers.max(1),
+            options,
+        }
+    }
+
+    /// Converts all documents. One failing document won't stop the conversion of the others.
+    /// Returns a report stating which documents were converted and which failed.
+    pub fn run(self) -> Report {
+        let total = self.jobs.len();
+        let queue = Arc::new(Mutex::new(VecDeque::from(self.jobs)));
+        let (tx, rx) = channel();
+        let mut workers = Vec::new();
+        for _ in 0..self.workers.min(total) {
+            let queue = Arc::clone(&queue);
+            let tx = tx.clone();
+            let options = self.options.clone();
+            workers.push(thread::spawn(move || loop {
+                let job = match queue.lock().unwrap().pop_front() {
+                    Some(x) => x,
+                    None => break,
+                };
+                if options.cancel.is_cancelled() {
+                    let _ = tx.send((job.input, Err(SmoothError::Cancelled.to_string())));
+                    continue;
+                }
+                let rsl = match File::new(
+                    job.input.as_str(),
+                    job.output.as_deref(),
+                    job.formats,
+                    options.clone(),
+                ) {
+                    Ok(x) => x.convert().map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                if tx.send((job.input, rsl)).is_err() {
+                    break;
+                }
+            }));
+        }
+        drop(tx);
+
+        let mut report = Report {
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+        };
+        for (input, rsl) in rx {
+            match rsl {
+                Ok(_) => {
+                    info!("converted {}", input);
+                    report.succeeded.push(input);
+                }
+                Err(e) => {
+                    debug!("failed {}: {}", input, e);
+                    report.failed.push((input, e));
+                }
+            }
+        }
+        for worker in workers {
+            let _ = worker.join();
+        }
+        report
+    }
+
+    /// Resolves one input argument to a list of file paths. Folders are searched recursively for
+    /// markdown files, glob patterns are expanded. All other inputs are returned as they are, the
+    /// existence of the file is checked later when converting.
+    fn expand_input(input: &'a str) -> Result<Vec<String>, SmoothError<'a>> {
+        let path = util::normalize_path(input, None)?;
+        let pattern = if path.is_dir() {
+            format!("{}/**/*", path.display())
+        } else if input.contains(&['*', '?', '['][..]) {
+            format!("{}", path.display())
+        } else {
+            return Ok(vec![String::from(input)]);
+        };
+
+        let entries = match glob(&pattern) {
+            Ok(x) => x,
+            Err(e) => return Err(SmoothError::InvalidGlob(input, e)),
+        };
+        let mut rsl: Vec<String> = entries
+            .filter_map(|x| x.ok())
+            .filter(|x| x.is_file())
+            .filter(|x| {
+                !path.is_dir()
+                    || x.extension()
+                        .and_then(|y| y.to_str())
+                        .map(|y| MARKDOWN_EXTENSIONS.contains(&y))
+                        .unwrap_or(false)
+            })
+            .filter_map(|x| x.to_str().map(String::from))
+            .collect();
+        if rsl.is_empty() {
+            warn!("no markdown files found for {}", input);
+        }
+        rsl.sort();
+        Ok(rsl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Batch;
+
+    use std::fs;
+
+    #[test]
+    fn expand_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |x: &str| dir.path().join(x).to_str().unwrap().to_string();
+        fs::create_dir_all(path("docs/sub")).unwrap();
+        for file in ["docs/b.md", "docs/a.markdown", "docs/sub/c.md", "docs/notes.txt"].iter() {
+            fs::write(path(file), "# Test\n").unwrap();
+        }
+
+        let folder = path("docs");
+        assert_eq!(
+            Batch::expand_input(&folder).unwrap(),
+            vec![path("docs/a.markdown"), path("docs/b.md"), path("docs/sub/c.md")]
+        );
+        let pattern = path("docs/*.md");
+        assert_eq!(Batch::expand_input(&pattern).unwrap(), vec![path("docs/b.md")]);
+        let missing = path("missing.md");
+        assert_eq!(Batch::expand_input(&missing).unwrap(), vec![missing.clone()]);
+    }
+}
@@ -12,6 +12,10 @@ const ODTPDF_FORMAT: &str = "odtpdf";
 /// Format argument for a Reveal.js output.
 const REVEAL_FORMAT: &str = "reveal";
 
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
 use clap::{App, AppSettings, Arg, ArgMatches, ValueHint};
 
 /// Default CLI interface for rsmooth-lib.
@@ -29,27 +33,10 @@ pub fn main() {
                 .short('d')
                 .global(true),
         )
-        .arg(
-            Arg::new("format")
-                .about("set the output format")
-                .long("format")
-                .short('f')
-                .takes_value(true)
-                .possible_value(PDF_FORMAT)
-                .possible_value(ODT_FORMAT)
-                .possible_value(DOCX_FORMAT)
-                .possible_value(ODTPDF_FORMAT)
-                .possible_value(REVEAL_FORMAT)
-                .default_value(PDF_FORMAT)
-        )
-        .arg(
-            Arg::new("INPUT")
-                .about("path to input markdown file")
-                .value_name("INPUT")
-                .required(true)
-                .index(1)
-                .value_hint(ValueHint::AnyPath),
-        )
+        .arg(format_arg())
+        .arg(input_arg().multiple(true).about(
+            "paths to input markdown files, folders or glob patterns (ex.: \"docs/**/*.md\")",
+        ))
         .arg(
             Arg::new("raw")
                 .about("outputs the finalized pandoc input on the stdout for debugging")
@@ -58,12 +45,55 @@ pub fn main() {
                 .global(true),
         )
         .arg(
-            Arg::new("output")
-                .about("optional output file path")
-                .long("output")
-                .short('o')
-                .takes_value(true),
+            Arg::new("strict")
+                .about("fails on misspelled or deprecated header fields instead of warning")
+                .long("strict")
+                .global(true),
+        )
+        .arg(
+            Arg::new("deny-warnings")
+                .about("fails when pandoc reports warnings (ex.: missing citations or images)")
+                .long("deny-warnings")
+                .global(true),
         )
+        .arg(
+            Arg::new("offline")
+                .about("uses only cached files for templates and other files referenced by URL")
+                .long("offline")
+                .global(true),
+        )
+        .arg(
+            Arg::new("refresh")
+                .about("downloads files referenced by URL again even if they are cached")
+                .long("refresh")
+                .conflicts_with("offline")
+                .global(true),
+        )
+        .arg(
+            Arg::new("timeout")
+                .about("stops pandoc and libreoffice when a conversion takes longer than the given number of seconds")
+                .long("timeout")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(|x| match x.parse::<f64>() {
+                    Ok(y) if y > 0.0 && y.is_finite() => Ok(()),
+                    _ => Err(String::from("the timeout has to be a positive number of seconds")),
+                })
+                .global(true),
+        )
+        .arg(
+            Arg::new("profile")
+                .about("applies the values of the given profile in the profiles field of the header")
+                .long("profile")
+                .short('p')
+                .value_name("NAME")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(output_arg())
+        .arg(jobs_arg())
+        .arg(context_arg())
+        .arg(set_arg())
         .subcommand(
             App::new("example-file")
                 .about("outputs a example markdown file with all available header fields")
@@ -75,6 +105,59 @@ pub fn main() {
                         .takes_value(true),
                 ),
         )
+        .subcommand(
+            App::new("build")
+                .about("builds all documents of the project described in the project file")
+                .arg(
+                    Arg::new("PROJECT")
+                        .about("path to the project file, defaults to rsmooth.yaml, rsmooth.yml or rsmooth.toml in the current folder")
+                        .value_name("PROJECT")
+                        .index(1)
+                        .value_hint(ValueHint::FilePath),
+                )
+                .arg(jobs_arg())
+                .arg(context_arg())
+                .arg(set_arg()),
+        )
+        .subcommand(
+            App::new("config")
+                .about("inspects the configuration of rsmooth")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    App::new("show")
+                        .about("prints the effective configuration and where each value comes from")
+                        .arg(
+                            input_arg()
+                                .required(false)
+                                .about("optional markdown file, includes the header values of the document"),
+                        )
+                        .arg(context_arg())
+                        .arg(set_arg()),
+                ),
+        )
+        .subcommand(
+            App::new("templates")
+                .about("inspects the templates which can be used by name")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    App::new("list")
+                        .about("lists the available templates and the folder each one comes from")
+                        .arg(
+                            input_arg()
+                                .required(false)
+                                .about("optional markdown file, lists the templates available to the document"),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("watch")
+                .about("converts the input file every time it or one of it's dependencies changes")
+                .arg(input_arg())
+                .arg(format_arg())
+                .arg(output_arg())
+                .arg(context_arg())
+                .arg(set_arg()),
+        )
         .get_matches();
 
     env_logger::Builder::new()
@@ -89,11 +172,188 @@ pub fn main() {
 
     match matches.subcommand() {
         Some(("example-file", x)) => example_cmd(x),
+        Some(("build", x)) => build_cmd(x),
+        Some(("watch", x)) => watch_cmd(x),
+        Some(("config", x)) => config_cmd(x),
+        Some(("templates", x)) => templates_cmd(x),
         Some((&_, _)) => {}
         None => default_cmd(&matches),
     }
 }
 
+/// Argument for the path to the input markdown file.
+fn input_arg<'a>() -> Arg<'a> {
+    Arg::new("INPUT")
+        .about("path to input markdown file")
+        .value_name("INPUT")
+        .required(true)
+        .index(1)
+        .value_hint(ValueHint::AnyPath)
+}
+
+/// Argument for the output format(s).
+fn format_arg<'a>() -> Arg<'a> {
+    Arg::new("format")
+        .about("set the output format, multiple formats are separated by commas (ex.: pdf,docx), defaults to the format field in the header or pdf")
+        .long("format")
+        .short('f')
+        .takes_value(true)
+        .multiple(true)
+        .use_delimiter(true)
+        .possible_value(PDF_FORMAT)
+        .possible_value(ODT_FORMAT)
+        .possible_value(DOCX_FORMAT)
+        .possible_value(ODTPDF_FORMAT)
+        .possible_value(REVEAL_FORMAT)
+}
+
+/// Argument for the optional output path.
+fn output_arg<'a>() -> Arg<'a> {
+    Arg::new("output")
+        .about("optional output file path")
+        .long("output")
+        .short('o')
+        .takes_value(true)
+}
+
+/// Argument for the number of documents converted at the same time.
+fn jobs_arg<'a>() -> Arg<'a> {
+    Arg::new("jobs")
+        .about("number of documents converted at the same time")
+        .long("jobs")
+        .short('j')
+        .takes_value(true)
+        .default_value("1")
+        .validator(|x| match x.parse::<usize>() {
+            Ok(y) if y > 0 => Ok(()),
+            _ => Err(String::from("number of jobs has to be a positive number")),
+        })
+}
+
+/// Argument for additional data files loaded into the Tera context or single context values.
+fn context_arg<'a>() -> Arg<'a> {
+    Arg::new("context")
+        .about("data file (YAML, JSON, TOML or CSV) loaded into the Tera context or a single value (key=value), can be given multiple times")
+        .long("context")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .value_hint(ValueHint::FilePath)
+}
+
+/// Argument for header values overriding the values of the document.
+fn set_arg<'a>() -> Arg<'a> {
+    Arg::new("set")
+        .about("sets a header value (key=value), nested values by a dotted key (ex.: tera_context.customer=ACME), can be given multiple times")
+        .long("set")
+        .short('M')
+        .value_name("KEY=VALUE")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+}
+
+/// Returns the options for the conversion as stated by the arguments.
+fn options(matches: &ArgMatches) -> lib::Options {
+    let mut overrides: Vec<String> = match matches.values_of("set") {
+        Some(x) => x.map(String::from).collect(),
+        None => vec![],
+    };
+    let mut context_files = Vec::new();
+    for value in matches.values_of("context").into_iter().flatten() {
+        match is_assignment(value) {
+            true => overrides.push(format!("tera_context.{}", value)),
+            false => context_files.push(String::from(value)),
+        }
+    }
+    lib::Options {
+        output_raw: matches.is_present("raw"),
+        strict: matches.is_present("strict"),
+        deny_warnings: matches.is_present("deny-warnings"),
+        profile: matches.value_of("profile").map(String::from),
+        context_files,
+        overrides,
+        cache_mode: match (matches.is_present("offline"), matches.is_present("refresh")) {
+            (true, _) => lib::CacheMode::Offline,
+            (_, true) => lib::CacheMode::Refresh,
+            _ => lib::CacheMode::Default,
+        },
+        timeout: matches
+            .value_of("timeout")
+            .and_then(|x| x.parse::<f64>().ok())
+            .map(Duration::from_secs_f64),
+        cancel: cancel_on_interrupt(),
+        ..lib::Options::default()
+    }
+}
+
+/// Returns a handle which gets cancelled when the user hits Ctrl-C. The running external tools
+/// are stopped and the temporary files removed before rsmooth exits. Hitting Ctrl-C a second time
+/// exits immediately.
+fn cancel_on_interrupt() -> lib::CancelHandle {
+    let handle = lib::CancelHandle::new();
+    let cancel = handle.clone();
+    let rsl = ctrlc::set_handler(move || {
+        if cancel.is_cancelled() {
+            process::exit(130);
+        }
+        warn!("cancelling the conversion, hit Ctrl-C again to exit immediately");
+        cancel.cancel();
+    });
+    if let Err(e) = rsl {
+        debug!("couldn't set the handler for Ctrl-C {}", e);
+    }
+    handle
+}
+
+/// Whether the value of the context argument is a single value (`key=value`) rather than the
+/// path to a data file. The key may only contain letters, digits, underscores, dashes and dots.
+fn is_assignment(value: &str) -> bool {
+    match value.find('=') {
+        Some(x) if x > 0 => value[..x]
+            .chars()
+            .all(|y| y.is_alphanumeric() || y == '_' || y == '-' || y == '.'),
+        _ => false,
+    }
+}
+
+/// Returns the output formats chosen by the format argument. Returns an empty list if the
+/// argument isn't set, thus the formats of the document header will be used.
+fn output_formats(matches: &ArgMatches) -> Vec<lib::OutputFormat> {
+    match matches.values_of("format") {
+        Some(x) => x.filter_map(|y| y.parse().ok()).collect(),
+        None => vec![],
+    }
+}
+
+/// Returns the number of jobs as stated by the jobs argument.
+fn jobs(matches: &ArgMatches) -> Option<usize> {
+    match matches.value_of("jobs").unwrap().parse::<usize>() {
+        Ok(x) if x > 0 => Some(x),
+        _ => {
+            error!("number of jobs has to be a positive number");
+            None
+        }
+    }
+}
+
+/// Logs the summary of a conversion of multiple documents. Exits with an error code if a document
+/// failed, this way a CI pipeline notices the failure.
+fn print_report(report: lib::Report) {
+    let failed = !report.failed.is_empty();
+    info!(
+        "{} of {} documents converted successfully",
+        report.succeeded.len(),
+        report.succeeded.len() + report.failed.len()
+    );
+    for (input, err) in report.failed {
+        error!("failed {}: {}", input, err);
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
 /// Handles example subcommand.
 fn example_cmd(matches: &ArgMatches) {
     match lib::example(matches.value_of("output")) {
@@ -105,22 +365,87 @@ fn example_cmd(matches: &ArgMatches) {
     }
 }
 
-/// Handles default command.
+/// Handles default command. A single input file is converted directly, multiple inputs, folders
+/// and glob patterns are handled as a batch. Exits with an error code if a document failed.
 fn default_cmd(matches: &ArgMatches) {
-    match lib::convert(
+    let inputs: Vec<&str> = matches.values_of("INPUT").unwrap().collect();
+    if inputs.len() == 1 && Path::new(inputs[0]).is_file() {
+        match lib::convert(
+            inputs[0],
+            matches.value_of("output"),
+            output_formats(matches),
+            options(matches),
+        ) {
+            Ok(_) => {}
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            }
+        };
+        return;
+    }
+
+    if matches.is_present("output") {
+        error!("the output path can only be set when converting a single file");
+        process::exit(1);
+    }
+    let jobs = match jobs(matches) {
+        Some(x) => x,
+        None => process::exit(1),
+    };
+    match lib::convert_batch(inputs, jobs, output_formats(matches), options(matches)) {
+        Ok(report) => print_report(report),
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Handles build subcommand. Exits with an error code if the project couldn't be loaded or a
+/// document failed.
+fn build_cmd(matches: &ArgMatches) {
+    let jobs = match jobs(matches) {
+        Some(x) => x,
+        None => process::exit(1),
+    };
+    match lib::build(matches.value_of("PROJECT"), jobs, options(matches)) {
+        Ok(report) => print_report(report),
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Handles config subcommand.
+fn config_cmd(matches: &ArgMatches) {
+    if let Some(("show", x)) = matches.subcommand() {
+        match lib::config(x.value_of("INPUT"), options(x)) {
+            Ok(x) => print!("{}", x),
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+/// Handles templates subcommand.
+fn templates_cmd(matches: &ArgMatches) {
+    if let Some(("list", x)) = matches.subcommand() {
+        match lib::templates(x.value_of("INPUT")) {
+            Ok(x) => print!("{}", x),
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+/// Handles watch subcommand.
+fn watch_cmd(matches: &ArgMatches) {
+    if let Err(e) = lib::watch(
         matches.value_of("INPUT").unwrap(),
         matches.value_of("output"),
-        matches.is_present("raw"),
-        match matches.value_of("format").unwrap() {
-            PDF_FORMAT => lib::OutputFormat::Pdf,
-            ODT_FORMAT => lib::OutputFormat::Odt,
-            DOCX_FORMAT => lib::OutputFormat::Docx,
-            ODTPDF_FORMAT => lib::OutputFormat::OdtPdf,
-            REVEAL_FORMAT => lib::OutputFormat::Reveal,
-            _ => lib::OutputFormat::Pdf,
-        },
+        output_formats(matches),
+        options(matches),
     ) {
-        Ok(_) => {}
-        Err(e) => error!("{}", e),
-    };
+        error!("{}", e)
+    }
 }
@@ -0,0 +1,115 @@
+/// This module reads template bundles. A bundle is a folder containing a manifest
+/// (`bundle.yaml`) which states the template, reference files, citation style, engine, Lua
+/// filters and asset folders of a design, thus a document can switch to it with a single field.
+use crate::error::SmoothError;
+use crate::frontmatter;
+use crate::metadata;
+use crate::registry::TemplateRegistry;
+use crate::util;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::value::Value;
+
+/// Names of the manifest file within a bundle folder in the order they're looked up.
+const BUNDLE_FILES: [&str; 2] = ["bundle.yaml", "bundle.yml"];
+
+/// A template bundle.
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    /// Absolute path to the manifest file.
+    pub path: PathBuf,
+    /// Header values of the bundle (ex.: `engine` or the `formats` blocks with the template and
+    /// reference files). Relative paths are already resolved relative to the bundle folder.
+    pub values: Value,
+    /// Absolute paths of the Lua filters of the bundle.
+    pub filters: Vec<PathBuf>,
+    /// Absolute paths of the asset folders of the bundle. They are added to the resource path of
+    /// pandoc.
+    pub assets: Vec<PathBuf>,
+}
+
+impl<'a> Bundle {
+    /// Returns the manifest file within the given folder, `None` if the folder isn't a bundle.
+    pub fn manifest(folder: &Path) -> Option<PathBuf> {
+        BUNDLE_FILES
+            .iter()
+            .map(|x| folder.join(x))
+            .find(|x| x.is_file())
+    }
+
+    /// Looks up the bundle stated in the `template_bundle` field. The value is either a path to
+    /// the bundle folder or manifest (relative to the given folder of the document) or the name
+    /// of a bundle folder within one of the folders of the template registry.
+    pub fn find(
+        bundle: &str,
+        wd: &PathBuf,
+        registry: &TemplateRegistry,
+    ) -> Result<Self, SmoothError<'a>> {
+        let path = util::normalize_path(bundle, Some(wd))?;
+        let manifest = match path.is_dir() {
+            true => Bundle::manifest(&path),
+            false if path.is_file() => Some(path),
+            false if !bundle.contains(&['/', '\\'][..]) => registry.find_bundle(bundle),
+            false => None,
+        };
+        match manifest {
+            Some(x) => Bundle::read(x),
+            None => Err(SmoothError::BundleNotFound(String::from(bundle))),
+        }
+    }
+
+    /// Reads the manifest of a bundle. The fields `filters` and `assets` are specific to bundles,
+    /// all other fields are header values. All paths are resolved relative to the bundle folder
+    /// and the filters and asset folders have to exist.
+    fn read(path: PathBuf) -> Result<Self, SmoothError<'a>> {
+        let content = match fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(e) => return Err(SmoothError::ReadSourceFailed(path, e)),
+        };
+        let mut values = match frontmatter::parse(&content) {
+            Ok(x) => x,
+            Err(e) => return Err(SmoothError::MetadataYamlParseFailure(path, e)),
+        };
+        let folder = match path.parent() {
+            Some(x) => x.to_path_buf(),
+            None => return Err(SmoothError::NoParentFolder(path)),
+        };
+        let filters = Bundle::paths(&mut values, "filters", &folder)?;
+        let assets = Bundle::paths(&mut values, "assets", &folder)?;
+        metadata::resolve_paths(&mut values, &folder);
+        Ok(Self {
+            path,
+            values,
+            filters,
+            assets,
+        })
+    }
+
+    /// Removes a field containing a path or a list of paths from the manifest values and returns
+    /// the paths resolved relative to the bundle folder.
+    fn paths(
+        values: &mut Value,
+        field: &str,
+        folder: &PathBuf,
+    ) -> Result<Vec<PathBuf>, SmoothError<'a>> {
+        let entries = match values.as_object_mut().and_then(|x| x.remove(field)) {
+            Some(Value::Array(x)) => x,
+            Some(x) => vec![x],
+            None => vec![],
+        };
+        let mut rsl = Vec::new();
+        for entry in entries {
+            let path = match entry.as_str() {
+                Some(x) if !x.trim().is_empty() => util::normalize_path(x, Some(folder))?,
+                _ => continue,
+            };
+            if !path.exists() {
+                return Err(SmoothError::BundleFileNotFound(path));
+            }
+            rsl.push(path);
+        }
+        Ok(rsl)
+    }
+}
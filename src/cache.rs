@@ -0,0 +1,272 @@
+/// This module downloads files referenced by URL (ex.: `template: https://example.com/a.tex`)
+/// into a local cache. The files are stored by the hash of their content, a small index maps
+/// every URL to the file of its last download. Downloads are done by curl.
+use crate::error::SmoothError;
+use crate::process::{self, CancelHandle, ProcessError};
+use crate::util;
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+use serde_json::value::Value;
+use tempfile::{Builder, NamedTempFile};
+
+/// Default name of the curl executable.
+pub const CURL_CMD: &str = "curl";
+
+/// Name of the environment variable which can be used to state the curl executable.
+pub const CURL_ENV: &str = "CURL_CMD";
+
+/// Header fields which can contain URLs.
+const URL_FIELDS: [&str; 4] = ["template", "reference", "csl", "bibliography"];
+
+/// Maximum time in seconds curl may take to connect to the server.
+const CONNECT_TIMEOUT: &str = "30";
+
+/// Maximum time in seconds a download may take in total.
+const DOWNLOAD_TIMEOUT: &str = "300";
+
+/// File extensions by the content type the server states, used for URLs without an extension.
+const CONTENT_TYPES: [(&str, &str); 9] = [
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+    ),
+    ("application/vnd.oasis.opendocument.text", "odt"),
+    ("application/vnd.citationstyles.style+xml", "csl"),
+    ("application/x-bibtex", "bib"),
+    ("text/x-bibtex", "bib"),
+    ("application/x-tex", "tex"),
+    ("text/x-tex", "tex"),
+    ("application/json", "json"),
+    ("application/x-yaml", "yaml"),
+];
+
+/// Path of the cache relative to the cache folder of the user (ex.: `~/.cache` on Linux).
+const CACHE_FOLDER: &str = "rsmooth";
+
+/// How the cache is used.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CacheMode {
+    /// Files are only downloaded if they aren't cached yet.
+    #[default]
+    Default,
+    /// Files are downloaded again even if they are cached.
+    Refresh,
+    /// Nothing is downloaded, only cached files are used.
+    Offline,
+}
+
+/// The download cache.
+pub struct Cache {
+    /// Folder of the cache.
+    folder: PathBuf,
+    /// Name or path of the curl executable.
+    curl: String,
+    /// How the cache is used.
+    mode: CacheMode,
+    /// The URLs downloaded by this instance, a URL is only refreshed once per conversion.
+    downloaded: RefCell<Vec<String>>,
+    /// Maximal time a download may take, curl is stopped afterwards.
+    timeout: Option<Duration>,
+    /// Handle to cancel running downloads.
+    cancel: CancelHandle,
+}
+
+impl<'a> Cache {
+    /// Returns the cache in the cache folder of the user using the given curl executable. Curl is
+    /// stopped when a download takes longer than the timeout or the handle gets cancelled.
+    pub fn new(
+        curl: String,
+        mode: CacheMode,
+        timeout: Option<Duration>,
+        cancel: CancelHandle,
+    ) -> Self {
+        Self {
+            folder: dirs::cache_dir()
+                .unwrap_or_else(std::env::temp_dir)
+                .join(CACHE_FOLDER),
+            curl,
+            mode,
+            downloaded: RefCell::new(vec![]),
+            timeout,
+            cancel,
+        }
+    }
+
+    /// Replaces the URLs in the fields `template`, `reference`, `csl` and `bibliography` of the
+    /// given header values with the paths of the cached files. Files not yet cached are
+    /// downloaded unless the cache is used offline.
+    pub fn localize(&self, header: &mut Value) -> Result<(), SmoothError<'a>> {
+        let fields = match header.as_object_mut() {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        for field in URL_FIELDS.iter() {
+            let values: Vec<&mut Value> = match fields.get_mut(*field) {
+                Some(Value::Array(x)) => x.iter_mut().collect(),
+                Some(x) => vec![x],
+                None => continue,
+            };
+            for value in values {
+                let url = match value.as_str() {
+                    Some(x) if util::is_url(x) => String::from(x.trim()),
+                    _ => continue,
+                };
+                *value = Value::from(self.get(field, &url)?.to_string_lossy());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the cached file for the given URL, downloading it if necessary. The
+    /// field is used for the error messages.
+    fn get(&self, field: &str, url: &str) -> Result<PathBuf, SmoothError<'a>> {
+        let cached = self.cached(url);
+        let refreshed = self.downloaded.borrow().iter().any(|x| x == url);
+        match (cached, self.mode) {
+            (Some(x), CacheMode::Default) | (Some(x), CacheMode::Offline) => return Ok(x),
+            (Some(x), CacheMode::Refresh) if refreshed => return Ok(x),
+            (None, CacheMode::Offline) => {
+                return Err(SmoothError::NotCached(String::from(field), String::from(url)))
+            }
+            _ => {}
+        }
+        let rsl = self.download(field, url)?;
+        self.downloaded.borrow_mut().push(String::from(url));
+        Ok(rsl)
+    }
+
+    /// Returns the cached file of the given URL if there is one.
+    fn cached(&self, url: &str) -> Option<PathBuf> {
+        let name = fs::read_to_string(self.index_path(url)).ok()?;
+        let path = self.folder.join("files").join(name.trim());
+        match path.is_file() {
+            true => Some(path),
+            false => None,
+        }
+    }
+
+    /// Downloads the given URL into the cache and returns the path of the cached file. Every
+    /// download goes into it's own temporary file which is then renamed, this way parallel
+    /// conversions can download the same URL at the same time.
+    fn download(&self, field: &str, url: &str) -> Result<PathBuf, SmoothError<'a>> {
+        debug!("downloading {} ({})", url, field);
+        let files = self.folder.join("files");
+        for folder in [&files, &self.folder.join("index")].iter() {
+            if let Err(e) = fs::create_dir_all(folder) {
+                return Err(SmoothError::FileCreateFailed(folder.to_path_buf(), e));
+            }
+        }
+        let partial = Cache::temporary_file(&files)?;
+        let mut cmd = Command::new(&self.curl);
+        cmd.args(["--fail", "--silent", "--show-error", "--location"])
+            .arg("--connect-timeout")
+            .arg(CONNECT_TIMEOUT)
+            .arg("--max-time")
+            .arg(DOWNLOAD_TIMEOUT)
+            .arg("--write-out")
+            .arg("%{content_type}")
+            .arg("--output")
+            .arg(partial.path())
+            .arg(url);
+        let failed = |reason: String| {
+            SmoothError::DownloadFailed(String::from(field), String::from(url), reason)
+        };
+        let content_type = match process::output(&mut cmd, self.timeout, &self.cancel) {
+            Ok(x) if x.status.success() => String::from_utf8_lossy(&x.stdout).to_string(),
+            Ok(x) => {
+                return Err(failed(
+                    String::from_utf8_lossy(&x.stderr).trim().to_string(),
+                ))
+            }
+            Err(ProcessError::Io(e)) if e.kind() == ErrorKind::NotFound => {
+                return Err(failed(format!(
+                    "couldn't find curl with the executable name \"{}\", use env \"{}\" or the configuration file to specify otherwise",
+                    self.curl, CURL_ENV
+                )))
+            }
+            Err(ProcessError::Io(e)) => return Err(failed(e.to_string())),
+            Err(ProcessError::Timeout(x)) => {
+                return Err(failed(format!(
+                    "curl was stopped after {} seconds",
+                    x.as_secs_f64()
+                )))
+            }
+            Err(ProcessError::Cancelled) => return Err(SmoothError::Cancelled),
+        };
+
+        let content = match fs::read(partial.path()) {
+            Ok(x) => x,
+            Err(e) => return Err(SmoothError::ReadSourceFailed(partial.path().to_path_buf(), e)),
+        };
+        let extension =
+            Cache::extension(url).or_else(|| Cache::fallback_extension(field, &content_type));
+        let name = match extension {
+            Some(x) => format!("{}.{}", util::hash(&content), x),
+            None => util::hash(&content),
+        };
+        let path = files.join(&name);
+        Cache::persist(partial, &path)?;
+
+        let index = self.index_path(url);
+        let mut file = Cache::temporary_file(&self.folder.join("index"))?;
+        if let Err(e) = file.write_all(name.as_bytes()) {
+            return Err(SmoothError::WriteFailed(file.path().to_path_buf(), e));
+        }
+        Cache::persist(file, &index)?;
+        Ok(path)
+    }
+
+    /// Creates a temporary file within the given folder of the cache. The file is removed on drop
+    /// unless it's persisted.
+    fn temporary_file(folder: &PathBuf) -> Result<NamedTempFile, SmoothError<'a>> {
+        match Builder::new().suffix(".part").tempfile_in(folder) {
+            Ok(x) => Ok(x),
+            Err(e) => Err(SmoothError::TemporaryFile(e)),
+        }
+    }
+
+    /// Atomically moves the temporary file to the given path, an existing file is replaced. As
+    /// the files are named by their content, a file created by a parallel download in the
+    /// meantime is as good as the own one.
+    fn persist(file: NamedTempFile, path: &PathBuf) -> Result<(), SmoothError<'a>> {
+        match file.persist(path) {
+            Ok(_) => Ok(()),
+            Err(_) if path.is_file() => Ok(()),
+            Err(e) => Err(SmoothError::FileCreateFailed(path.clone(), e.error)),
+        }
+    }
+
+    /// Path of the index file of a URL holding the name of the cached file.
+    fn index_path(&self, url: &str) -> PathBuf {
+        self.folder.join("index").join(util::hash(url.as_bytes()))
+    }
+
+    /// Returns the file extension for a download whose URL has none. It's derived from the content
+    /// type stated by the server, citation styles are recognized by the field.
+    fn fallback_extension(field: &str, content_type: &str) -> Option<String> {
+        let mime = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+        match CONTENT_TYPES.iter().find(|(x, _)| *x == mime) {
+            Some((_, x)) => Some(String::from(*x)),
+            None if field == "csl" => Some(String::from("csl")),
+            None => None,
+        }
+    }
+
+    /// Returns the file extension of the last segment of a URL's path. The extension is kept as
+    /// pandoc and rsmooth determine the file type by it (ex.: for reference files).
+    fn extension(url: &str) -> Option<String> {
+        let path = url.split(&['?', '#'][..]).next().unwrap_or(url);
+        let path = path.split_once("://").map(|x| x.1).unwrap_or(path);
+        let name = path.split_once('/')?.1.rsplit('/').next()?;
+        match name.rfind('.') {
+            Some(x) if x > 0 && x + 1 < name.len() => Some(name[x + 1..].to_lowercase()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,221 @@
+/// This module handles the per-user configuration file holding default header values, the
+/// executables of the external tools and named template directories.
+use crate::cache::{CURL_CMD, CURL_ENV};
+use crate::error::SmoothError;
+use crate::libreoffice::{LIBREOFFICE_CMD, LIBREOFFICE_ENV};
+use crate::metadata;
+use crate::pandoc::{PANDOC_CMD, PANDOC_ENV};
+use crate::util::{self, OneOrMany};
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde_json::value::Value;
+
+/// Name of the environment variable which can be used to point to a configuration file other
+/// than the default one.
+const CONFIG_ENV: &str = "RSMOOTH_CONFIG";
+
+/// Path of the configuration file relative to the configuration folder of the user (ex.:
+/// `~/.config` on Linux).
+const CONFIG_FILE: &str = "rsmooth/config.yaml";
+
+/// Folder of the citation styles within the user data folder of pandoc.
+const PANDOC_STYLES: &str = "pandoc/csl";
+
+/// Content of the configuration file.
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    /// Header values applied to all documents.
+    defaults: Option<Value>,
+    /// Name or path of the pandoc executable.
+    pandoc: Option<String>,
+    /// Name or path of the LibreOffice executable.
+    libreoffice: Option<String>,
+    /// Name or path of the curl executable.
+    curl: Option<String>,
+    /// Template folders by their name.
+    #[serde(default)]
+    templates: BTreeMap<String, String>,
+    /// Folder(s) containing citation styles.
+    styles: Option<OneOrMany<String>>,
+}
+
+/// The configuration of the current user. Applies to every conversion unless the document or
+/// the environment states otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Path to the configuration file, `None` if no configuration file exists.
+    pub path: Option<PathBuf>,
+    /// Header values applied to all documents. Relative paths are already resolved relative to
+    /// the configuration file.
+    pub defaults: Option<Value>,
+    /// Name or path of the pandoc executable.
+    pandoc: Option<String>,
+    /// Name or path of the LibreOffice executable.
+    libreoffice: Option<String>,
+    /// Name or path of the curl executable used to download files referenced by URL.
+    curl: Option<String>,
+    /// Absolute paths of the template folders by their name. A path field of the header can
+    /// refer to a file in such a folder by using the `@name/file` notation.
+    pub templates: BTreeMap<String, PathBuf>,
+    /// Absolute paths of the folders containing citation styles which can be referred to by
+    /// name.
+    pub styles: Vec<PathBuf>,
+}
+
+impl<'a> Config {
+    /// Reads the configuration file of the current user. The file is located at
+    /// `rsmooth/config.yaml` within the configuration folder of the operating system or at the
+    /// path given by the `RSMOOTH_CONFIG` environment variable. Returns an empty configuration
+    /// if the default file doesn't exist.
+    pub fn load() -> Result<Self, SmoothError<'a>> {
+        let path = match env::var(CONFIG_ENV) {
+            Ok(x) => {
+                let path = util::normalize_path(x, None)?;
+                if !path.exists() {
+                    return Err(SmoothError::ConfigNotFound(path));
+                }
+                path
+            }
+            Err(_) => match dirs::config_dir() {
+                Some(x) if x.join(CONFIG_FILE).exists() => x.join(CONFIG_FILE),
+                _ => return Ok(Config::default()),
+            },
+        };
+        let content = match fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(e) => return Err(SmoothError::ReadSourceFailed(path, e)),
+        };
+        let file = match serde_yaml::from_str::<Option<ConfigFile>>(&content) {
+            Ok(Some(x)) => x,
+            Ok(None) => ConfigFile::default(),
+            Err(e) => return Err(SmoothError::ConfigParseFailure(path, e)),
+        };
+        let parent = match path.parent() {
+            Some(x) => x.to_path_buf(),
+            None => return Err(SmoothError::NoParentFolder(path)),
+        };
+
+        let mut defaults = file.defaults;
+        if let Some(ref mut x) = defaults {
+            metadata::resolve_paths(x, &parent);
+        }
+        let mut templates = BTreeMap::new();
+        for (name, folder) in file.templates {
+            templates.insert(name, util::normalize_path(folder, Some(&parent))?);
+        }
+        let mut styles = Vec::new();
+        for folder in file.styles.map(|x| x.into_vec()).unwrap_or_default() {
+            styles.push(util::normalize_path(folder, Some(&parent))?);
+        }
+        Ok(Self {
+            path: Some(path),
+            defaults,
+            pandoc: Config::resolve_executable(file.pandoc, &parent)?,
+            libreoffice: Config::resolve_executable(file.libreoffice, &parent)?,
+            curl: Config::resolve_executable(file.curl, &parent)?,
+            templates,
+            styles,
+        })
+    }
+
+    /// Returns the pandoc executable. The `PANDOC_CMD` environment variable takes priority over
+    /// the configuration file.
+    pub fn pandoc(&self) -> String {
+        self.executable(PANDOC_ENV, &self.pandoc, PANDOC_CMD).0
+    }
+
+    /// Returns the LibreOffice executable. The `LIBREOFFICE_CMD` environment variable takes
+    /// priority over the configuration file.
+    pub fn libreoffice(&self) -> String {
+        self.executable(LIBREOFFICE_ENV, &self.libreoffice, LIBREOFFICE_CMD)
+            .0
+    }
+
+    /// Returns the curl executable. The `CURL_CMD` environment variable takes priority over the
+    /// configuration file.
+    pub fn curl(&self) -> String {
+        self.executable(CURL_ENV, &self.curl, CURL_CMD).0
+    }
+
+    /// Returns the executables of the external tools with a description of where each value
+    /// originates from.
+    pub fn executables(&self) -> Vec<(&'static str, String, String)> {
+        vec![
+            ("pandoc", PANDOC_ENV, &self.pandoc, PANDOC_CMD),
+            ("libreoffice", LIBREOFFICE_ENV, &self.libreoffice, LIBREOFFICE_CMD),
+            ("curl", CURL_ENV, &self.curl, CURL_CMD),
+        ]
+        .into_iter()
+        .map(|(name, var, configured, default)| {
+            let (value, source) = self.executable(var, configured, default);
+            (name, value, source)
+        })
+        .collect()
+    }
+
+    /// Returns the folders in which citation styles given by name are looked up. These are the
+    /// folders of the configuration followed by the `csl` folder in the user data folder of
+    /// pandoc (ex.: `~/.local/share/pandoc/csl` on Linux).
+    pub fn style_folders(&self) -> Vec<PathBuf> {
+        let mut rsl = self.styles.clone();
+        if let Some(x) = dirs::data_dir() {
+            rsl.push(x.join(PANDOC_STYLES));
+        }
+        rsl
+    }
+
+    /// Replaces the name of a template folder at the beginning of a path (`@name/file`) with the
+    /// path of the folder. Paths not starting with an `@` are returned unaltered.
+    pub fn expand_path(&self, path: &str) -> Result<String, SmoothError<'a>> {
+        let rest = match path.strip_prefix('@') {
+            Some(x) => x,
+            None => return Ok(String::from(path)),
+        };
+        let (name, file) = match rest.find(&['/', '\\'][..]) {
+            Some(x) => (&rest[..x], &rest[x + 1..]),
+            None => (rest, ""),
+        };
+        match self.templates.get(name) {
+            Some(x) => Ok(x.join(file).to_string_lossy().into_owned()),
+            None => Err(SmoothError::UnknownTemplateFolder(String::from(name))),
+        }
+    }
+
+    /// Executables given as a path (containing a path separator) are resolved relative to the
+    /// configuration file, plain names are looked up by the system.
+    fn resolve_executable(
+        executable: Option<String>,
+        wd: &PathBuf,
+    ) -> Result<Option<String>, SmoothError<'a>> {
+        match executable {
+            Some(x) if x.contains(&['/', '\\'][..]) => Ok(Some(
+                util::normalize_path(x, Some(wd))?
+                    .to_string_lossy()
+                    .into_owned(),
+            )),
+            x => Ok(x),
+        }
+    }
+
+    /// Determines an executable. The environment variable takes priority over the configured
+    /// value which takes priority over the default. Returns the executable and it's origin.
+    fn executable(
+        &self,
+        var: &str,
+        configured: &Option<String>,
+        default: &str,
+    ) -> (String, String) {
+        if let Ok(x) = env::var(var) {
+            return (x, format!("environment variable {}", var));
+        }
+        match (configured, &self.path) {
+            (Some(x), Some(path)) => (x.clone(), format!("user config {}", path.display())),
+            _ => (String::from(default), String::from("default")),
+        }
+    }
+}
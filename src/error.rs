@@ -1,6 +1,7 @@
 use crate::pandoc::PandocError;
 use crate::libreoffice::LibreOfficeError;
 use crate::util::NormalizeError;
+use crate::OutputFormat;
 
 use std::convert::From;
 use std::error::Error;
@@ -8,8 +9,13 @@ use std::fmt;
 use std::io::Error as IOError;
 use std::path::PathBuf;
 
+use glob::PatternError;
+use notify::Error as NotifyError;
 use serde_json::error::Error as JsonError;
+use serde_yaml::Error as YamlError;
 use tera::Error as TeraError;
+use yaml_rust::ScanError;
+use toml::de::Error as TomlError;
 
 /// The error type for errors which can occur while running rsmooth.
 pub enum SmoothError<'a> {
@@ -25,29 +31,46 @@ pub enum SmoothError<'a> {
     /// Couldn't read the Frontmatter YAML Header of the input file. String resembles the path to
     /// the input file.
     MetadataRead(&'a str),
-    /// Occurs when the JSON template is already present in the temporary folder. See the matedata
-    /// module for more information. Contains the path to the template file.
-    JsonTemplateExists(PathBuf),
-    /// Error occurring while the creation of the metadata as JSON template. First element contains
-    /// path to the file, the second element contains the std::io::Error with the cause.
-    CreateJsonTemplateFailed(PathBuf, IOError),
-    /// Occurs when the converting metadata JSON cannot be parsed.
+    /// The YAML front matter of the input file or a file it extends couldn't be parsed. Contains
+    /// the path to the file and the cause.
+    MetadataYamlParseFailure(PathBuf, ScanError),
+    /// Occurs when the parsed front matter doesn't match the expected fields and types.
     MetadataParseFailure(JsonError),
-    /// Error for failing of the metadata as JSON template removal. Contains the path to the
-    /// template file and the cause.
-    RemoveJsonTemplateFailed(PathBuf, IOError),
-    /// The given template path as specified in the metadata header was not found with the given
-    /// path.
+    /// The template as specified in the metadata header was not found. Contains the path or, for
+    /// a template given by name, the name which was searched for.
     TemplateNotFound(PathBuf),
+    /// The template bundle stated in the `template_bundle` field wasn't found. Contains the name
+    /// or path of the bundle.
+    BundleNotFound(String),
+    /// A filter or asset folder stated in the manifest of a template bundle doesn't exist.
+    BundleFileNotFound(PathBuf),
     /// The given reference path as specified in the metadata header was not found with the given
     /// path.
     ReferenceNotFound(PathBuf),
     /// The given bibliography path as specified in the metadata header was not found with the given
     /// path.
     BibliographyNotFound(PathBuf),
-    /// The given citation style file path as specified in the metadata header was not found with the given
-    /// path.
+    /// The citation style as specified in the metadata header was not found. Contains the path or,
+    /// for a style given by name, the file name which was searched for.
     CitationStyleNotFound(PathBuf),
+    /// The theme file of the syntax highlighting (`highlight_style` ending in `.theme`) was not
+    /// found with the given path.
+    HighlightThemeNotFound(PathBuf),
+    /// A file to be included into the output (`include_in_header` or `include_before_body`) was
+    /// not found with the given path.
+    IncludeNotFound(PathBuf),
+    /// A metadata file as specified in the `metadata_files` field was not found with the given
+    /// path.
+    MetadataFileNotFound(PathBuf),
+    /// A header field has a value of the wrong type. Contains the name of the field, where it was
+    /// set and a description of the expected type.
+    InvalidFieldType(String, String, &'static str),
+    /// The validation of the header resulted in warnings while running in strict mode. Contains
+    /// the warnings.
+    HeaderValidationFailed(Vec<String>),
+    /// A header field contains a value which cannot be used. Contains the name of the field, the
+    /// value and a description of the expected values.
+    InvalidFieldValue(String, String, &'static str),
     /// Error while creating a temporary file. Contains the error.
     TemporaryFile(IOError),
     /// Couldn't read source file.
@@ -66,6 +89,71 @@ pub enum SmoothError<'a> {
     /// reference file for a docx export. First parameter contains the path to the faulty reference
     /// file the second describes the output format.
     IncompatibleReferenceFile(PathBuf, &'a str),
+    /// The given input isn't a valid glob pattern. Contains the input and the cause.
+    InvalidGlob(&'a str, PatternError),
+    /// No project file was found under the given path.
+    ProjectNotFound(PathBuf),
+    /// The YAML project file under the given path couldn't be parsed.
+    ProjectYamlParseFailure(PathBuf, YamlError),
+    /// The TOML project file under the given path couldn't be parsed.
+    ProjectTomlParseFailure(PathBuf, TomlError),
+    /// The given name doesn't describe a known output format.
+    UnknownOutputFormat(String),
+    /// The effective header values couldn't be serialized for pandoc.
+    SerializeHeader(YamlError),
+    /// The configuration file given by the `RSMOOTH_CONFIG` environment variable doesn't exist.
+    ConfigNotFound(PathBuf),
+    /// The user configuration file couldn't be parsed.
+    ConfigParseFailure(PathBuf, YamlError),
+    /// A path refers to a template folder (`@name/file`) which isn't defined in the user
+    /// configuration. Contains the name of the folder.
+    UnknownTemplateFolder(String),
+    /// The profile selected for the conversion isn't defined in the `profiles` field. Contains
+    /// the name of the profile and the names of the defined profiles.
+    UnknownProfile(String, Vec<String>),
+    /// Pandoc reported warnings while warnings are denied (`--deny-warnings`). Contains the
+    /// warnings.
+    WarningsDenied(Vec<String>),
+    /// The conversion was cancelled (ex.: by Ctrl-C).
+    Cancelled,
+    /// The JSON AST of the document returned by pandoc couldn't be parsed. Contains the cause.
+    AstParseFailure(JsonError),
+    /// A filter implemented in Rust failed. Contains the name of the filter and the cause.
+    FilterFailed(String, String),
+    /// A filter stated in the `filters` field wasn't found. Contains the path to the filter.
+    FilterNotFound(PathBuf),
+    /// The `filters` field names a filter which isn't part of the filter library. Contains the
+    /// name and the names of the available filters.
+    UnknownFilter(String, Vec<&'static str>),
+    /// A value to set isn't given in the form `key=value`. Contains the given assignment.
+    InvalidAssignment(String),
+    /// The installed pandoc doesn't fulfill the `pandoc_version` requirement of the header.
+    /// Contains the requirement and the detected version.
+    PandocVersionMismatch(String, String),
+    /// A file referenced by URL couldn't be downloaded. Contains the header field, the URL and
+    /// the cause.
+    DownloadFailed(String, String, String),
+    /// A file referenced by URL isn't in the cache while running offline. Contains the header
+    /// field and the URL.
+    NotCached(String, String),
+    /// A data file for the Tera context wasn't found.
+    ContextFileNotFound(PathBuf),
+    /// The type of a data file for the Tera context isn't supported. Contains the path to the
+    /// file.
+    UnsupportedContextFile(PathBuf),
+    /// A data file for the Tera context couldn't be parsed. Contains the path to the file and
+    /// the cause.
+    ContextFileParseFailure(PathBuf, String),
+    /// A file stated in the `extends` field of a header wasn't found.
+    ExtendsNotFound(PathBuf),
+    /// The files of the `extends` fields are extending each other. Contains the chain of files
+    /// ending with the file which closes the cycle.
+    ExtendsCycle(Vec<PathBuf>),
+    /// The conversion into one or more output formats failed while converting into multiple
+    /// formats. Contains the failed formats with their respective error.
+    FormatsFailed(Vec<(OutputFormat, SmoothError<'a>)>),
+    /// Error occurring while watching the input file and it's dependencies for changes.
+    Watch(NotifyError),
 }
 
 
@@ -99,31 +187,30 @@ impl fmt::Display for SmoothError<'_> {
                 "YAML header for input file \"{}\" couldn't be read",
                 path
             ),
-            SmoothError::JsonTemplateExists(path) => write!(
+            SmoothError::MetadataYamlParseFailure(path, err) => write!(
                 f,
-                "pandoc template for extracting the metadata as JSON already present under \"{}\" please remove this file manually before proceeding",
-                path.display()
-            ),
-            SmoothError::CreateJsonTemplateFailed(path, why) => write!(
-                f,
-                "couldn't write temporary metadata-as-JSON template to {} {}",
+                "couldn't parse YAML header of {} {}",
                 path.display(),
-                why
+                err
             ),
             SmoothError::MetadataParseFailure(err) => write!(
                 f,
                 "couldn't parse frontmatter metadata header of document {}",
                 err
             ),
-            SmoothError::RemoveJsonTemplateFailed(path, why) => write!(
+            SmoothError::TemplateNotFound(path) => write!(
                 f,
-                "couldn't remove temporary metadata-as-JSON template under {} {}",
-                path.display(),
-                why
+                "couldn't find template {}",
+                path.display()
             ),
-            SmoothError::TemplateNotFound(path) => write!(
+            SmoothError::BundleNotFound(name) => write!(
+                f,
+                "couldn't find template bundle {}",
+                name
+            ),
+            SmoothError::BundleFileNotFound(path) => write!(
                 f,
-                "couldn't find template file under {}",
+                "couldn't find file of template bundle under {}",
                 path.display()
             ),
             SmoothError::ReferenceNotFound(path) => write!(
@@ -138,9 +225,39 @@ impl fmt::Display for SmoothError<'_> {
             ),
             SmoothError::CitationStyleNotFound(path) => write!(
                 f,
-                "couldn't find citation style (csl) file under {}",
+                "couldn't find citation style (csl) {}",
                 path.display()
             ),
+            SmoothError::HighlightThemeNotFound(path) => write!(
+                f,
+                "couldn't find highlight theme under {}",
+                path.display()
+            ),
+            SmoothError::IncludeNotFound(path) => write!(
+                f,
+                "couldn't find file to include under {}",
+                path.display()
+            ),
+            SmoothError::MetadataFileNotFound(path) => write!(
+                f,
+                "couldn't find metadata file under {}",
+                path.display()
+            ),
+            SmoothError::InvalidFieldType(field, source, expected) => write!(
+                f,
+                "header field {} ({}) has to be {}",
+                field, source, expected
+            ),
+            SmoothError::HeaderValidationFailed(warnings) => write!(
+                f,
+                "header validation failed (strict mode): {}",
+                warnings.join("; ")
+            ),
+            SmoothError::InvalidFieldValue(field, value, expected) => write!(
+                f,
+                "invalid value {} for header field {}, expected {}",
+                value, field, expected
+            ),
             SmoothError::TemporaryFile(err) => write!(
                 f,
                 "couldn't create temporary file {}",
@@ -180,6 +297,157 @@ impl fmt::Display for SmoothError<'_> {
                 file.display(),
                 format
             ),
+            SmoothError::InvalidGlob(input, err) => write!(
+                f,
+                "input \"{}\" isn't a valid glob pattern {}",
+                input,
+                err
+            ),
+            SmoothError::ProjectNotFound(path) => write!(
+                f,
+                "couldn't find project file under {}",
+                path.display()
+            ),
+            SmoothError::ProjectYamlParseFailure(path, err) => write!(
+                f,
+                "couldn't parse project file {} {}",
+                path.display(),
+                err
+            ),
+            SmoothError::ProjectTomlParseFailure(path, err) => write!(
+                f,
+                "couldn't parse project file {} {}",
+                path.display(),
+                err
+            ),
+            SmoothError::UnknownOutputFormat(name) => write!(
+                f,
+                "unknown output format \"{}\"",
+                name
+            ),
+            SmoothError::SerializeHeader(err) => write!(
+                f,
+                "couldn't pass the header values to pandoc {}",
+                err
+            ),
+            SmoothError::ConfigNotFound(path) => write!(
+                f,
+                "configuration file {} not found",
+                path.display()
+            ),
+            SmoothError::ConfigParseFailure(path, err) => write!(
+                f,
+                "couldn't parse configuration file {} {}",
+                path.display(),
+                err
+            ),
+            SmoothError::UnknownTemplateFolder(name) => write!(
+                f,
+                "template folder \"{}\" is not defined in the templates section of the configuration file",
+                name
+            ),
+            SmoothError::UnknownProfile(name, available) => match available.is_empty() {
+                true => write!(f, "profile \"{}\" not found, no profiles are defined", name),
+                false => write!(
+                    f,
+                    "profile \"{}\" not found, available profiles: {}",
+                    name,
+                    available.join(", ")
+                ),
+            },
+            SmoothError::WarningsDenied(warnings) => write!(
+                f,
+                "conversion failed on {} warning(s) (deny warnings): {}",
+                warnings.len(),
+                warnings.join("; ")
+            ),
+            SmoothError::Cancelled => write!(f, "conversion cancelled"),
+            SmoothError::AstParseFailure(err) => write!(
+                f,
+                "couldn't read the document AST returned by pandoc {}",
+                err
+            ),
+            SmoothError::FilterFailed(name, reason) => write!(
+                f,
+                "filter {} failed: {}",
+                name, reason
+            ),
+            SmoothError::FilterNotFound(path) => write!(
+                f,
+                "couldn't find filter {}",
+                path.display()
+            ),
+            SmoothError::UnknownFilter(name, available) => write!(
+                f,
+                "unknown filter \"{}\", available filters: {}",
+                name,
+                available.join(", ")
+            ),
+            SmoothError::InvalidAssignment(assignment) => write!(
+                f,
+                "invalid assignment \"{}\", expected key=value",
+                assignment
+            ),
+            SmoothError::PandocVersionMismatch(required, detected) => write!(
+                f,
+                "the document requires pandoc {} but pandoc {} is installed",
+                required, detected
+            ),
+            SmoothError::DownloadFailed(field, url, reason) => write!(
+                f,
+                "couldn't download {} of header field {}: {}",
+                url, field, reason
+            ),
+            SmoothError::NotCached(field, url) => write!(
+                f,
+                "{} of header field {} isn't cached, run without --offline to download it",
+                url, field
+            ),
+            SmoothError::ContextFileNotFound(path) => write!(
+                f,
+                "couldn't find Tera context file under {}",
+                path.display()
+            ),
+            SmoothError::UnsupportedContextFile(path) => write!(
+                f,
+                "unsupported type of Tera context file {}, use YAML, JSON, TOML or CSV",
+                path.display()
+            ),
+            SmoothError::ContextFileParseFailure(path, err) => write!(
+                f,
+                "couldn't parse Tera context file {} {}",
+                path.display(),
+                err
+            ),
+            SmoothError::ExtendsNotFound(path) => write!(
+                f,
+                "file to extend {} not found",
+                path.display()
+            ),
+            SmoothError::ExtendsCycle(chain) => write!(
+                f,
+                "cycle in extended headers: {}",
+                chain
+                    .iter()
+                    .map(|x| x.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(" -> ")
+            ),
+            SmoothError::FormatsFailed(errors) => write!(
+                f,
+                "conversion failed for {} format(s): {}",
+                errors.len(),
+                errors
+                    .iter()
+                    .map(|(format, err)| format!("{}: {}", format.name(), err))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            SmoothError::Watch(err) => write!(
+                f,
+                "couldn't watch files for changes {}",
+                err
+            ),
         }
     }
 }
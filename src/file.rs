@@ -1,25 +1,38 @@
+use crate::ast::{BreakDescription, Filter};
+use crate::cache::Cache;
 use crate::error::SmoothError;
+use crate::frontmatter;
+use crate::latex::{self, Location, Report, SourceMap};
 use crate::libreoffice::LibreOffice;
-use crate::metadata::Metadata;
-use crate::pandoc::Pandoc;
-use crate::tera::Template;
+use crate::metadata::{self, Header, Layers, Metadata};
+use crate::pandoc::{Pandoc, PandocError};
+use crate::registry::TemplateRegistry;
+use crate::tera::{self, Template};
 use crate::util;
-use crate::OutputFormat;
+use crate::validate;
+use crate::warning::Warning;
+use crate::version::Requirement;
+use crate::{Options, OutputFormat};
 
 use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
 
-use tempfile::NamedTempFile;
+use serde_json::value::Value;
+use tempfile::{Builder, NamedTempFile};
 
 /// Describes the (root) markdown file which should be converted.
 pub struct File {
     /// Absolute path to the markdown source file.
     path: PathBuf,
-    /// Destination path for the output file.
-    output_path: PathBuf,
-    /// Desired format of the output file.
-    output_format: OutputFormat,
+    /// Optional destination path for the output file.
+    output_path: Option<PathBuf>,
+    /// Desired formats of the output file. If empty the formats stated in the header are used.
+    output_formats: Vec<OutputFormat>,
+    /// Additional settings for the conversion.
+    options: Options,
 }
 
 impl<'a> File {
@@ -28,7 +41,8 @@ impl<'a> File {
     pub fn new<S: Into<&'a str>>(
         path: S,
         output_path: Option<S>,
-        output_format: OutputFormat,
+        output_formats: Vec<OutputFormat>,
+        options: Options,
     ) -> Result<Self, SmoothError<'a>> {
         let in_path = path.into();
         let norm_in_path = util::normalize_path(in_path, None)?;
@@ -36,73 +50,556 @@ impl<'a> File {
             return Err(SmoothError::InputFileNotFound(in_path, norm_in_path));
         }
         Ok(Self {
-            path: norm_in_path.clone(),
+            path: norm_in_path,
             output_path: match output_path {
-                Some(x) => util::normalize_path(x.into(), None)?,
-                None => File::out_path_from_input(norm_in_path, &output_format),
+                Some(x) => Some(util::normalize_path(x.into(), None)?),
+                None => None,
             },
-            output_format: output_format,
+            output_formats,
+            options,
         })
     }
 
-    /// Converts the loaded markdown file. The keep_temp parameter states whether the temporary
-    /// pandoc input file should be kept for debugging purposes.
-    pub fn convert(self, output_raw: bool) -> Result<(), SmoothError<'a>> {
-        let metadata = Metadata::from(&self.path, &self.parent_folder()?, &self.output_format)?;
+    /// Converts the loaded markdown file into all requested output formats. The header is read
+    /// and the Tera templating applied only once. For every format the effective header values
+    /// (defaults, extended files, the document and the block of the format in the `formats`
+    /// field) are written as front matter of the prepared input, thus pandoc sees the same values
+    /// as rsmooth. A failing format doesn't stop the conversion into the other formats.
+    pub fn convert(self) -> Result<(), SmoothError<'a>> {
+        let mut layers = self.layers()?;
+        validate::validate(&layers, self.options.strict)?;
+        let header = Header::from(layers.merged())?;
+        let formats = self.formats(&header)?;
 
-        let mut content = self.read_source()?;
+        let source = self.read_source()?;
+        let mut content = source.clone();
 
-        if metadata.do_tera {
-            content = Template::new(&self.path, metadata.clone().tera_context)?.apply(content)?;
+        if header.do_tera {
+            let mut context = tera::build_context(
+                &self.tera_context_files(&header)?,
+                header.tera_context.clone(),
+            )?;
+            if let Some(ref x) = self.options.profile {
+                context.insert(String::from("profile"), Value::from(x.as_str()));
+            }
+            content = Template::new(&self.path, Some(context))?.apply(content)?;
         }
-
-        let mut current = File::new_named_tempfile()?;
-        match current.write_all(content.as_bytes()) {
-            Ok(_) => {}
-            Err(e) => return Err(SmoothError::WriteFailed(current.path().to_path_buf(), e)),
+        let body = match frontmatter::split(&content) {
+            (Some(x), body) => {
+                if header.do_tera {
+                    match frontmatter::parse(x) {
+                        Ok(y) => layers.set_document(y, &self.options.config)?,
+                        Err(e) => {
+                            return Err(SmoothError::MetadataYamlParseFailure(self.path.clone(), e))
+                        }
+                    }
+                }
+                body
+            }
+            (None, body) => body,
         };
-        let prepared_input = current.path().to_path_buf();
+        let tera_files = match header.do_tera {
+            true => Some(Template::new(&self.path, None)?.dependencies(&source)),
+            false => None,
+        };
+        let sources = SourceMap::new(self.path.clone(), source, &content, body, tera_files);
+
+        let pandoc = self.pandoc(&header)?;
+        let registry = TemplateRegistry::new(&self.parent_folder()?, &self.options.config);
+        let cache = self.cache();
+        let mut failed = Vec::new();
+        for format in formats.iter() {
+            if self.options.cancel.is_cancelled() {
+                return Err(SmoothError::Cancelled);
+            }
+            let output = self.output_path(format, formats.len() > 1);
+            let mut values = layers.merged_for(format);
+            let rsl = cache
+                .localize(&mut values)
+                .and_then(|_| Header::from(values.clone()))
+                .and_then(|x| {
+                    Metadata::from(
+                        x,
+                        &self.parent_folder()?,
+                        format,
+                        &self.options.config,
+                        &registry,
+                    )
+                })
+                .and_then(|mut metadata| {
+                    if let Some(x) = layers.bundle() {
+                        metadata.lua_filters = x.filters.clone();
+                        metadata.resource_paths = x.assets.clone();
+                    }
+                    let input = self.prepare(&values, body)?;
+                    self.convert_to(
+                        &pandoc,
+                        &input.path().to_path_buf(),
+                        metadata,
+                        format,
+                        &output,
+                        &sources,
+                    )
+                });
+            if let Err(e) = rsl {
+                if formats.len() == 1 {
+                    return Err(e);
+                }
+                failed.push((*format, e));
+            }
+        }
+
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(SmoothError::FormatsFailed(failed)),
+        }
+    }
 
-        if output_raw {
+    /// Writes the prepared pandoc input consisting of the given header values and the body into
+    /// a temporary file.
+    fn prepare(&self, header: &Value, body: &str) -> Result<NamedTempFile, SmoothError<'a>> {
+        let content = match frontmatter::join(header, body) {
+            Ok(x) => x,
+            Err(e) => return Err(SmoothError::SerializeHeader(e)),
+        };
+        if self.options.output_raw {
             println!("{}", content)
         }
+        let mut file = File::new_named_tempfile()?;
+        match file.write_all(content.as_bytes()) {
+            Ok(_) => Ok(file),
+            Err(e) => Err(SmoothError::WriteFailed(file.path().to_path_buf(), e)),
+        }
+    }
 
-        let result = match self.output_format {
-            OutputFormat::Pdf => Pandoc::new().convert_with_metadata_to_pdf(
-                &prepared_input,
-                metadata,
-                &self.output_path,
+    /// Converts the prepared input into pandoc's JSON AST and applies the given filters
+    /// implemented in Rust. Returns the temporary file containing the resulting AST, the warnings
+    /// pandoc reported are added to the given list. The input format and the timeout are taken
+    /// from the metadata.
+    fn apply_filters(
+        &self,
+        pandoc: &Pandoc,
+        prepared_input: &PathBuf,
+        filters: &[Arc<dyn Filter>],
+        format: &OutputFormat,
+        metadata: &Metadata,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<NamedTempFile, SmoothError<'a>> {
+        let json = match pandoc.convert_to_json(prepared_input, metadata) {
+            Ok((x, y)) => {
+                warnings.extend(y);
+                x
+            }
+            Err(e) => return Err(SmoothError::Pandoc(e)),
+        };
+        let mut document = match serde_json::from_str::<Value>(&json) {
+            Ok(x) => x,
+            Err(e) => return Err(SmoothError::AstParseFailure(e)),
+        };
+        for filter in filters {
+            debug!("applying filter {}", filter.name());
+            if let Err(e) = filter.apply(&mut document, format) {
+                return Err(SmoothError::FilterFailed(String::from(filter.name()), e));
+            }
+        }
+        let mut file = match Builder::new().suffix(".json").tempfile() {
+            Ok(x) => x,
+            Err(e) => return Err(SmoothError::TemporaryFile(e)),
+        };
+        match file.write_all(document.to_string().as_bytes()) {
+            Ok(_) => Ok(file),
+            Err(e) => Err(SmoothError::WriteFailed(file.path().to_path_buf(), e)),
+        }
+    }
+
+    /// Detects the installed pandoc and checks it against the `pandoc_version` requirement of the
+    /// header. An unknown version only results in a warning.
+    fn pandoc(&self, header: &Header) -> Result<Pandoc, SmoothError<'a>> {
+        let pandoc = match Pandoc::detect(
+            self.options.config.pandoc(),
+            self.options.cancel.clone(),
+        ) {
+            Ok(x) => x,
+            Err(e) => return Err(SmoothError::Pandoc(e)),
+        };
+        let required = match header.pandoc_version {
+            Some(ref x) if !x.is_empty() => x,
+            _ => return Ok(pandoc),
+        };
+        let requirement = match required.parse::<Requirement>() {
+            Ok(x) => x,
+            Err(_) => {
+                return Err(SmoothError::InvalidFieldValue(
+                    String::from("pandoc_version"),
+                    required.clone(),
+                    "a version requirement like \">=3.1\"",
+                ))
+            }
+        };
+        match pandoc.version() {
+            Some(x) if !requirement.matches(x) => Err(SmoothError::PandocVersionMismatch(
+                required.clone(),
+                x.to_string(),
+            )),
+            Some(_) => Ok(pandoc),
+            None => {
+                warn!(
+                    "couldn't check the pandoc_version requirement {}, version of pandoc unknown",
+                    required
+                );
+                Ok(pandoc)
+            }
+        }
+    }
+
+    /// Converts the prepared input into one output format. A failed PDF conversion is explained
+    /// using the sources of the prepared input.
+    fn convert_to(
+        &self,
+        pandoc: &Pandoc,
+        prepared_input: &PathBuf,
+        mut metadata: Metadata,
+        format: &OutputFormat,
+        output: &PathBuf,
+        sources: &SourceMap,
+    ) -> Result<(), SmoothError<'a>> {
+        if let Some(x) = output.parent() {
+            if !x.exists() {
+                if let Err(e) = fs::create_dir_all(x) {
+                    return Err(SmoothError::FileCreateFailed(x.to_path_buf(), e));
+                }
+            }
+        }
+
+        if let Some(ref x) = self.options.profile {
+            metadata
+                .variables
+                .push((String::from("profile"), Some(x.clone())));
+        }
+        if self.options.timeout.is_some() {
+            metadata.timeout = self.options.timeout;
+        }
+        let timeout = metadata.timeout;
+
+        let mut ast_filters: Vec<Arc<dyn Filter>> = Vec::new();
+        if metadata.break_description {
+            ast_filters.push(Arc::new(BreakDescription));
+        }
+        ast_filters.extend(self.options.filters.iter().cloned());
+        let mut warnings = Vec::new();
+        // The filtered input has to live until pandoc is done as it's file is removed on drop.
+        let filtered_input = match ast_filters.is_empty() {
+            true => None,
+            false => Some(self.apply_filters(
+                pandoc,
+                prepared_input,
+                &ast_filters,
+                format,
+                &metadata,
+                &mut warnings,
+            )?),
+        };
+        let input = match filtered_input {
+            Some(ref x) => x.path().to_path_buf(),
+            None => prepared_input.clone(),
+        };
+        // Pandoc states the lines of it's input, for the prepared input they are mapped back to
+        // the markdown source. The lines of the filtered input (JSON) can't be mapped.
+        let offset = File::body_offset(prepared_input);
+        let mut warnings = File::locate_warnings(warnings, offset, sources);
+        let final_offset = match filtered_input {
+            Some(_) => None,
+            None => offset,
+        };
+
+        let started = Instant::now();
+        let result = match format {
+            OutputFormat::Pdf => match pandoc.convert_with_metadata_to_pdf(
+                &input,
+                metadata.clone(),
+                output,
                 Some(&self.parent_folder()?),
-            ),
-            OutputFormat::Odt | OutputFormat::Docx | OutputFormat::OdtPdf => Pandoc::new()
+            ) {
+                Err(PandocError::ExecutionFailed(mut info)) => {
+                    // The analysis only gets the time left of the timeout.
+                    metadata.timeout = timeout.map(|x| x.saturating_sub(started.elapsed()));
+                    if let Some(x) =
+                        self.explain_failure(pandoc, &input, metadata, info.stderr(), sources)
+                    {
+                        info.explain(&x);
+                    }
+                    Err(PandocError::ExecutionFailed(info))
+                }
+                x => x,
+            },
+            OutputFormat::Odt | OutputFormat::Docx | OutputFormat::OdtPdf => pandoc
                 .convert_with_metadata_to_office(
-                    &prepared_input,
+                    &input,
                     metadata,
-                    &self.output_path,
+                    output,
                     Some(&self.parent_folder()?),
                 ),
-            OutputFormat::Reveal => Pandoc::new().convert_with_metadata_to_reveal(
-                &prepared_input,
+            OutputFormat::Reveal => pandoc.convert_with_metadata_to_reveal(
+                &input,
                 metadata,
-                &self.output_path,
+                output,
                 Some(&self.parent_folder()?),
             ),
         };
 
         match result {
-            Ok(_) => (),
+            Ok(x) => warnings.extend(File::locate_warnings(x, final_offset, sources)),
             Err(e) => return Err(SmoothError::Pandoc(e)),
         }
 
-        if let OutputFormat::OdtPdf = self.output_format {
-            let office = LibreOffice::new();
-            match office.convert_to_pdf(&self.output_path) {
+        if let OutputFormat::OdtPdf = format {
+            let office = LibreOffice::new(
+                self.options.config.libreoffice(),
+                self.options.cancel.clone(),
+            );
+            match office.convert_to_pdf(output, timeout) {
                 Ok(_) => (),
                 Err(e) => return Err(SmoothError::LibreOffice(e)),
             }
         }
 
-        Ok(())
+        self.report_warnings(format, output, warnings)
+    }
+
+    /// Explains a failed PDF conversion. The LaTeX generated by pandoc is written into a folder
+    /// which is kept after rsmooth exits, the LaTeX engine is run on it and the first error of the
+    /// log is mapped back to the markdown source. Falls back to the output of pandoc if the
+    /// engine couldn't be run. Pandoc and the engine together are stopped when they run longer
+    /// than the timeout of the metadata. Returns `None` if the engine isn't a LaTeX engine or no
+    /// error was found.
+    fn explain_failure(
+        &self,
+        pandoc: &Pandoc,
+        input: &PathBuf,
+        metadata: Metadata,
+        stderr: &str,
+        sources: &SourceMap,
+    ) -> Option<Report> {
+        if !latex::is_latex(&metadata.engine) {
+            return None;
+        }
+        let parent = self.parent_folder().ok()?;
+        let folder = match Builder::new().prefix("rsmooth-latex-").tempdir() {
+            // Renamed to `keep` in newer versions of tempfile, `into_path` is kept for the
+            // versions before 3.20.
+            #[allow(deprecated)]
+            Ok(x) => x.into_path(),
+            Err(_) => return None,
+        };
+        let name = self.path.file_stem().unwrap_or_default();
+        let tex = folder.join(name).with_extension("tex");
+        let engine = metadata.engine.clone();
+        let started = Instant::now();
+        let timeout = metadata.timeout;
+        let log = match pandoc.convert_with_metadata_to_latex(input, metadata, &tex, Some(&parent))
+        {
+            Ok(_) => {
+                let timeout = timeout.map(|x| x.saturating_sub(started.elapsed()));
+                latex::compile(&engine, &tex, &parent, timeout, &self.options.cancel)
+            }
+            Err(_) => None,
+        };
+        let error = match log.as_deref().and_then(latex::first_error) {
+            Some(x) => x,
+            None => match latex::first_error(stderr) {
+                // The output of pandoc contains the error as well, it's kept instead of the log.
+                Some(x) => {
+                    let _ = fs::write(tex.with_extension("log"), stderr);
+                    x
+                }
+                None => {
+                    let _ = fs::remove_dir_all(&folder);
+                    return None;
+                }
+            },
+        };
+        let location = fs::read_to_string(&tex)
+            .ok()
+            .and_then(|x| latex::locate(&error, &x, sources));
+        Some(Report {
+            error,
+            location,
+            folder,
+        })
+    }
+
+    /// Logs the warnings reported while the conversion into the given format. If warnings are
+    /// denied (see `Options::deny_warnings`) the output is removed, as it might contain
+    /// placeholders (ex.: `???` for a missing citation), and an error is returned.
+    fn report_warnings(
+        &self,
+        format: &OutputFormat,
+        output: &PathBuf,
+        warnings: Vec<(Warning, Option<Location>)>,
+    ) -> Result<(), SmoothError<'a>> {
+        for (warning, location) in &warnings {
+            match location {
+                Some(x) => warn!(
+                    "{} ({}): {} (near {})",
+                    self.path.display(),
+                    format.name(),
+                    warning,
+                    x
+                ),
+                None => warn!("{} ({}): {}", self.path.display(), format.name(), warning),
+            }
+        }
+        if warnings.is_empty() || !self.options.deny_warnings {
+            return Ok(());
+        }
+        let _ = fs::remove_file(output);
+        if let OutputFormat::OdtPdf = format {
+            let _ = fs::remove_file(output.with_extension("pdf"));
+        }
+        Err(SmoothError::WarningsDenied(
+            warnings.iter().map(|(x, _)| x.to_string()).collect(),
+        ))
+    }
+
+    /// Returns the number of lines of the prepared input before the body (the front matter).
+    /// `None` if the input can't be read.
+    fn body_offset(prepared_input: &PathBuf) -> Option<usize> {
+        let content = fs::read_to_string(prepared_input).ok()?;
+        let (_, body) = frontmatter::split(&content);
+        Some(content[..content.len() - body.len()].matches('\n').count())
+    }
+
+    /// Maps the lines of the input stated in the warnings to the markdown source. The offset is
+    /// the number of lines of the input before the body, `None` if the lines of the input can't
+    /// be mapped.
+    fn locate_warnings(
+        warnings: Vec<Warning>,
+        offset: Option<usize>,
+        sources: &SourceMap,
+    ) -> Vec<(Warning, Option<Location>)> {
+        warnings
+            .into_iter()
+            .map(|x| {
+                let location = match (x.location, offset) {
+                    (Some((line, _)), Some(y)) if line > y => sources.body_line(line - y - 1),
+                    _ => None,
+                };
+                (x, location)
+            })
+            .collect()
+    }
+
+    /// Returns the paths of all files the conversion of the document depends upon. This includes
+    /// the markdown file itself, the extended headers, the files referenced in the header and, if
+    /// Tera is enabled, all files pulled in by the template engine.
+    pub fn dependencies(&self) -> Result<Vec<PathBuf>, SmoothError<'a>> {
+        let layers = self.layers()?;
+        let header = Header::from(layers.merged())?;
+        let registry = TemplateRegistry::new(&self.parent_folder()?, &self.options.config);
+        let cache = self.cache();
+        let mut rsl = vec![self.path.clone()];
+        rsl.extend(layers.files());
+        for format in self.formats(&header)?.iter() {
+            let mut values = layers.merged_for(format);
+            cache.localize(&mut values)?;
+            let metadata = Metadata::from(
+                Header::from(values)?,
+                &self.parent_folder()?,
+                format,
+                &self.options.config,
+                &registry,
+            )?;
+            for file in metadata.files() {
+                if !rsl.contains(&file) {
+                    rsl.push(file);
+                }
+            }
+        }
+        if header.do_tera {
+            rsl.extend(self.tera_context_files(&header)?);
+            let content = self.read_source()?;
+            rsl.extend(Template::new(&self.path, None)?.dependencies(&content));
+        }
+        Ok(rsl)
+    }
+
+    /// Returns the document and the files referenced in the path fields of it's own front matter,
+    /// whether they exist or not. Used by the watch mode when the dependencies can't be
+    /// determined (ex.: the template is missing), this way creating the missing file triggers a
+    /// rebuild.
+    pub fn referenced_files(&self) -> Vec<PathBuf> {
+        let mut rsl = vec![self.path.clone()];
+        let (source, parent) = match (self.read_source(), self.parent_folder()) {
+            (Ok(x), Ok(y)) => (x, y),
+            _ => return rsl,
+        };
+        if let (Some(x), _) = frontmatter::split(&source) {
+            if let Ok(header) = frontmatter::parse(x) {
+                rsl.extend(metadata::referenced_paths(&header, &parent));
+            }
+        }
+        rsl
+    }
+
+    /// Reads the header values of the document applying the default values, the profile and the
+    /// overrides of the options as well as the template bundle.
+    fn layers(&self) -> Result<Layers, SmoothError<'a>> {
+        let mut rsl = Layers::read(
+            &self.path,
+            &self.options.config,
+            self.options.defaults.as_ref(),
+        )?;
+        if let Some(ref x) = self.options.profile {
+            rsl.select_profile(x)?;
+        }
+        rsl.set_overrides(&self.options.overrides, &self.options.config)?;
+        let parent = self.parent_folder()?;
+        rsl.apply_bundle(&parent, &TemplateRegistry::new(&parent, &self.options.config))?;
+        Ok(rsl)
+    }
+
+    /// Returns the download cache for files referenced by URL.
+    fn cache(&self) -> Cache {
+        Cache::new(
+            self.options.config.curl(),
+            self.options.cache_mode,
+            self.options.timeout,
+            self.options.cancel.clone(),
+        )
+    }
+
+    /// Returns the paths of the data files for the Tera context. These are the files stated in
+    /// the header followed by the files given in the options (relative to the working directory).
+    fn tera_context_files(&self, header: &Header) -> Result<Vec<PathBuf>, SmoothError<'a>> {
+        let mut rsl = header.tera_context_files(&self.parent_folder()?)?;
+        for file in self.options.context_files.iter() {
+            rsl.push(util::normalize_path(file.as_str(), None)?);
+        }
+        Ok(rsl)
+    }
+
+    /// Returns the formats the document should be converted into. These are the formats given
+    /// while creating the file object, the formats stated in the header or PDF.
+    fn formats(&self, header: &Header) -> Result<Vec<OutputFormat>, SmoothError<'a>> {
+        if !self.output_formats.is_empty() {
+            return Ok(self.output_formats.clone());
+        }
+        let rsl = header.formats()?;
+        match rsl.is_empty() {
+            true => Ok(vec![OutputFormat::Pdf]),
+            false => Ok(rsl),
+        }
+    }
+
+    /// Returns the output path for the given format. If no output path was given, the path is
+    /// derived from the input file. When converting into multiple formats the extension of the
+    /// given output path is replaced according to the format.
+    fn output_path(&self, format: &OutputFormat, multiple: bool) -> PathBuf {
+        match self.output_path {
+            Some(ref x) if !multiple => x.clone(),
+            Some(ref x) => File::out_path_from_input(x.clone(), format),
+            None => File::out_path_from_input(self.path.clone(), format),
+        }
     }
 
     /// Reads the input file and returns the content as a string. This is used to apply all
@@ -0,0 +1,81 @@
+/// This module contains the library of Lua filters embedded into rsmooth, a document enables them
+/// by name in the `filters` field. They are written into temporary files for the time of a
+/// conversion and passed to pandoc.
+use crate::error::SmoothError;
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tempfile::{Builder, NamedTempFile};
+
+/// The filter library by the names used in the `filters` header field.
+const LIBRARY: [(&str, &str); 4] = [
+    ("pagebreak", include_str!("filters/pagebreak.lua")),
+    ("smallcaps", include_str!("filters/smallcaps.lua")),
+    ("landscape", include_str!("filters/landscape.lua")),
+    (
+        "include-code-file",
+        include_str!("filters/include_code_file.lua"),
+    ),
+];
+
+/// An entry of the filter pipeline stated in the `filters` header field. Pandoc applies the
+/// entries in the given order.
+#[derive(Debug, Clone)]
+pub enum PandocFilter {
+    /// A filter of the embedded library, already saved into it's temporary file. The file is
+    /// removed when the last clone of the entry is dropped.
+    Library(Arc<LuaFilter>),
+    /// A Lua filter file.
+    Lua(PathBuf),
+    /// A JSON filter executable.
+    Json(PathBuf),
+    /// The citation processing of pandoc (`citeproc`).
+    Citeproc,
+}
+
+/// An embedded Lua filter saved into a temporary file. The file is removed when the filter is
+/// dropped.
+#[derive(Debug)]
+pub struct LuaFilter(NamedTempFile);
+
+impl<'a> LuaFilter {
+    /// Returns the filter of the library with the given name.
+    pub fn named(name: &str) -> Result<Self, SmoothError<'a>> {
+        match LIBRARY.iter().find(|(x, _)| *x == name) {
+            Some((_, code)) => LuaFilter::new(code),
+            None => Err(SmoothError::UnknownFilter(
+                String::from(name),
+                LuaFilter::names(),
+            )),
+        }
+    }
+
+    /// Returns the names of all filters of the library.
+    pub fn names() -> Vec<&'static str> {
+        LIBRARY.iter().map(|(x, _)| *x).collect()
+    }
+
+    /// Whether the library contains a filter with the given name.
+    pub fn is_library(name: &str) -> bool {
+        LIBRARY.iter().any(|(x, _)| *x == name)
+    }
+
+    /// Saves the given filter code into a temporary file.
+    fn new(code: &str) -> Result<Self, SmoothError<'a>> {
+        let mut file = match Builder::new().suffix(".lua").tempfile() {
+            Ok(x) => x,
+            Err(e) => return Err(SmoothError::TemporaryFile(e)),
+        };
+        match file.write_all(code.as_bytes()) {
+            Ok(_) => Ok(Self(file)),
+            Err(e) => Err(SmoothError::WriteFailed(file.path().to_path_buf(), e)),
+        }
+    }
+
+    /// Returns the path to the temporary file of the filter.
+    pub fn path(&self) -> PathBuf {
+        self.0.path().to_path_buf()
+    }
+}
@@ -0,0 +1,46 @@
+-- Replaces the content of code blocks with an `include` attribute by the content of the given
+-- file (ex.: ```` ```{.rust include="src/main.rs" start_line=10 end_line=20} ````). The optional
+-- attributes `start_line` and `end_line` limit the included lines. Relative paths are looked up
+-- in the resource paths (the folder of the document). Part of the filter library of rsmooth
+-- (`filters: [include-code-file]`).
+
+local function open(path)
+  if path:match('^/') or path:match('^%a:[/\\]') then
+    return io.open(path, 'r')
+  end
+  for _, folder in ipairs(PANDOC_STATE.resource_path or {}) do
+    local file = io.open(folder .. '/' .. path, 'r')
+    if file then
+      return file
+    end
+  end
+  return io.open(path, 'r')
+end
+
+function CodeBlock(el)
+  local path = el.attributes.include
+  if not path then
+    return nil
+  end
+  local file = open(path)
+  if not file then
+    io.stderr:write('[WARNING] include-code-file: could not open ' .. path .. '\n')
+    return nil
+  end
+  local first = tonumber(el.attributes.start_line) or 1
+  local last = tonumber(el.attributes.end_line) or math.huge
+  local lines = {}
+  local number = 0
+  for line in file:lines() do
+    number = number + 1
+    if number >= first and number <= last then
+      table.insert(lines, line)
+    end
+  end
+  file:close()
+  el.attributes.include = nil
+  el.attributes.start_line = nil
+  el.attributes.end_line = nil
+  el.text = table.concat(lines, '\n')
+  return el
+end
@@ -0,0 +1,43 @@
+-- Sets the content of divs with the class `landscape` (ex.: a wide table) on pages in landscape
+-- orientation. Uses the pdflscape package for LaTeX output, other formats keep the content as is.
+-- Part of the filter library of rsmooth (`filters: [landscape]`).
+
+local used = false
+
+-- Whether a meta value is a list. Pandoc 2.17 and newer represent lists as plain tables.
+local function is_list(value)
+  if pandoc.utils.type then
+    return pandoc.utils.type(value) == 'List'
+  end
+  return value.t == 'MetaList'
+end
+
+function Div(el)
+  if not el.classes:includes('landscape') or not FORMAT:match('latex') then
+    return nil
+  end
+  used = true
+  local blocks = {pandoc.RawBlock('latex', '\\begin{landscape}')}
+  for _, block in ipairs(el.content) do
+    table.insert(blocks, block)
+  end
+  table.insert(blocks, pandoc.RawBlock('latex', '\\end{landscape}'))
+  return blocks
+end
+
+function Meta(meta)
+  if not used then
+    return nil
+  end
+  local package = pandoc.MetaBlocks({pandoc.RawBlock('latex', '\\usepackage{pdflscape}')})
+  local includes = meta['header-includes']
+  if includes == nil then
+    meta['header-includes'] = pandoc.MetaList({package})
+  elseif is_list(includes) then
+    table.insert(includes, package)
+    meta['header-includes'] = includes
+  else
+    meta['header-includes'] = pandoc.MetaList({includes, package})
+  end
+  return meta
+end
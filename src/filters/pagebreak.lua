@@ -0,0 +1,35 @@
+-- Turns a paragraph consisting only of `\newpage` or `\pagebreak` into a page break of the output
+-- format. Without the filter such a break only works for LaTeX output. Part of the filter library
+-- of rsmooth (`filters: [pagebreak]`).
+
+local function is_break(text)
+  local command = text:gsub('{}', ''):match('^%s*(\\%a+)%s*$')
+  return command == '\\newpage' or command == '\\pagebreak'
+end
+
+local function pagebreak()
+  if FORMAT:match('latex') or FORMAT:match('beamer') then
+    return pandoc.RawBlock('latex', '\\newpage{}')
+  elseif FORMAT:match('docx') then
+    return pandoc.RawBlock('openxml', '<w:p><w:r><w:br w:type="page"/></w:r></w:p>')
+  elseif FORMAT:match('odt') or FORMAT:match('opendocument') then
+    return pandoc.RawBlock('opendocument', '<text:p text:style-name="Pagebreak"/>')
+  elseif FORMAT:match('html') or FORMAT:match('revealjs') then
+    return pandoc.RawBlock('html', '<div style="page-break-after: always;"></div>')
+  end
+  return {}
+end
+
+function RawBlock(el)
+  if el.format:match('tex') and is_break(el.text) then
+    return pagebreak()
+  end
+end
+
+function Para(el)
+  local inline = el.content[1]
+  if #el.content == 1 and inline.t == 'RawInline' and inline.format:match('tex')
+      and is_break(inline.text) then
+    return pagebreak()
+  end
+end
@@ -0,0 +1,21 @@
+-- Sets spans with the class `sc` (ex.: `[Ada Lovelace]{.sc}`) and words written in capital
+-- letters (ex.: abbreviations like NASA) in small caps. Part of the filter library of rsmooth
+-- (`filters: [smallcaps]`).
+
+function Span(el)
+  if el.classes:includes('sc') then
+    return pandoc.SmallCaps(el.content)
+  end
+end
+
+function Str(el)
+  local word, rest = el.text:match('^(%u%u+)(%p*)$')
+  if not word then
+    return nil
+  end
+  local caps = pandoc.SmallCaps({pandoc.Str(word:lower())})
+  if rest == '' then
+    return caps
+  end
+  return {caps, pandoc.Str(rest)}
+end
@@ -0,0 +1,225 @@
+/// This module reads the YAML front matter of markdown documents. The parsed values are converted
+/// the same way pandoc interprets YAML metadata, thus rsmooth sees the same values pandoc does.
+/// The one exception are strings: pandoc reads them as markdown (`*x*` is emphasized text),
+/// rsmooth keeps the raw string. Earlier versions read the values through pandoc's `$meta-json$`
+/// which rendered such strings as HTML (`*x*` became `<em>x</em>`, `A & B` became `A &amp; B`),
+/// this mangled paths and the values of the Tera context. Pandoc itself still reads the strings
+/// as markdown, as the header is passed on unchanged.
+use std::collections::HashMap;
+
+use serde_json::value::{Map, Value};
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::{Marker, ScanError, TScalarStyle};
+
+/// Splits a markdown document into it's YAML front matter and the remaining body. The front
+/// matter has to start on the first line with three dashes (`---`) which are not followed by a
+/// blank line and ends with a line of three dashes or dots (`...`). Returns `None` as front matter
+/// if the document doesn't start with a YAML block.
+pub fn split(content: &str) -> (Option<&str>, &str) {
+    let mut lines = content.split_inclusive('\n');
+    match lines.next() {
+        Some(x) if x.trim_end() == "---" => {}
+        _ => return (None, content),
+    }
+    let start = content.find('\n').map(|x| x + 1).unwrap_or(content.len());
+    let mut pos = start;
+    for (i, line) in lines.enumerate() {
+        if i == 0 && line.trim().is_empty() {
+            return (None, content);
+        }
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            return (Some(&content[start..pos]), &content[pos + line.len()..]);
+        }
+        pos += line.len();
+    }
+    (None, content)
+}
+
+/// Parses a YAML front matter into a JSON value. As pandoc does, numbers are converted into
+/// strings, null values into empty strings and fields ending with an underscore are ignored.
+/// Booleans are recognized following the YAML 1.2 core schema (`true`, `True`, `TRUE` and the
+/// same for false). Returns an empty object if the YAML doesn't describe a mapping.
+pub fn parse(yaml: &str) -> Result<Value, ScanError> {
+    let mut builder = Builder::default();
+    Parser::new(yaml.chars()).load(&mut builder, false)?;
+    match builder.root {
+        Some(Value::Object(x)) => Ok(Value::Object(x)),
+        _ => Ok(Value::Object(Map::new())),
+    }
+}
+
+/// Joins the given header values and the body into a markdown document. The header is written as
+/// YAML front matter, no front matter is written if the header is empty.
+pub fn join(header: &Value, body: &str) -> Result<String, serde_yaml::Error> {
+    if header.as_object().map(|x| x.is_empty()).unwrap_or(true) {
+        return Ok(String::from(body));
+    }
+    let yaml = serde_yaml::to_string(header)?;
+    Ok(format!(
+        "---\n{}---\n{}",
+        yaml.strip_prefix("---\n").unwrap_or(&yaml),
+        body
+    ))
+}
+
+/// Receives the events of the YAML parser and builds up the resulting value.
+#[derive(Default)]
+struct Builder {
+    /// Collections which are currently built. Mappings store the pending key.
+    stack: Vec<(Value, Option<String>, usize)>,
+    /// Values of all anchors by their id.
+    anchors: HashMap<usize, Value>,
+    /// The finished root value.
+    root: Option<Value>,
+}
+
+impl Builder {
+    /// Adds a finished value to the current collection or sets it as root value.
+    fn insert(&mut self, value: Value, anchor: usize) {
+        if anchor > 0 {
+            self.anchors.insert(anchor, value.clone());
+        }
+        match self.stack.last_mut() {
+            Some((Value::Array(x), _, _)) => x.push(value),
+            Some((Value::Object(x), key, _)) => match key.take() {
+                Some(k) => {
+                    if !k.ends_with('_') {
+                        x.insert(k, value);
+                    }
+                }
+                None => {
+                    *key = Some(match value {
+                        Value::String(y) => y,
+                        y => y.to_string(),
+                    })
+                }
+            },
+            _ => self.root = Some(value),
+        }
+    }
+
+    /// Whether the next scalar is used as the key of a mapping.
+    fn expects_key(&self) -> bool {
+        match self.stack.last() {
+            Some((Value::Object(_), key, _)) => key.is_none(),
+            _ => false,
+        }
+    }
+}
+
+impl MarkedEventReceiver for Builder {
+    fn on_event(&mut self, ev: Event, _mark: Marker) {
+        match ev {
+            Event::SequenceStart(anchor) => self.stack.push((Value::Array(vec![]), None, anchor)),
+            Event::MappingStart(anchor) => {
+                self.stack.push((Value::Object(Map::new()), None, anchor))
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Some((value, _, anchor)) = self.stack.pop() {
+                    self.insert(value, anchor);
+                }
+            }
+            Event::Scalar(value, style, anchor, tag) => {
+                let value = match (style, tag, self.expects_key()) {
+                    (TScalarStyle::Plain, None, false) => resolve_plain(&value),
+                    _ => Value::String(value),
+                };
+                self.insert(value, anchor);
+            }
+            Event::Alias(anchor) => {
+                let value = self.anchors.get(&anchor).cloned().unwrap_or(Value::Null);
+                self.insert(value, 0);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Resolves a plain (unquoted) scalar the way pandoc does.
+fn resolve_plain(value: &str) -> Value {
+    match value {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::from(""),
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        _ => {}
+    }
+    let unsigned = value.trim_start_matches(&['-', '+'][..]);
+    let negative = value.starts_with('-');
+    let int = if let Some(x) = unsigned.strip_prefix("0x") {
+        i64::from_str_radix(x, 16).ok()
+    } else if let Some(x) = unsigned.strip_prefix("0o") {
+        i64::from_str_radix(x, 8).ok()
+    } else if unsigned.chars().all(|x| x.is_ascii_digit()) && unsigned.len() + 1 >= value.len() {
+        unsigned.parse::<i64>().ok()
+    } else {
+        None
+    };
+    if let Some(x) = int {
+        return Value::from(match negative {
+            true => (-x).to_string(),
+            false => x.to_string(),
+        });
+    }
+    if value.chars().any(|x| x.is_ascii_digit()) && !value.contains(char::is_whitespace) {
+        if let Ok(x) = value.parse::<f64>() {
+            return Value::from(match x.fract() == 0.0 && x.abs() < 1e7 {
+                true => format!("{:.1}", x),
+                false => x.to_string(),
+            });
+        }
+    }
+    Value::from(value.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    #[test]
+    fn split() {
+        let (yaml, body) = super::split("---\ntitle: Test\n...\n# Body\n");
+        assert_eq!(yaml, Some("title: Test\n"));
+        assert_eq!(body, "# Body\n");
+        assert_eq!(super::split("---\n\nNo header\n---\n").0, None);
+        assert_eq!(super::split("# No header\n").0, None);
+    }
+
+    #[test]
+    fn join() {
+        let header = json!({"title": "42", "toc": true});
+        let content = super::join(&header, "# Body\n").unwrap();
+        let (yaml, body) = super::split(&content);
+        assert_eq!(super::parse(yaml.unwrap()).unwrap(), header);
+        assert_eq!(body, "# Body\n");
+        assert_eq!(super::join(&json!({}), "# Body\n").unwrap(), "# Body\n");
+    }
+
+    #[test]
+    fn raw_strings() {
+        let yaml = "title: \"*Emphasis* & more\"\ntera_context:\n  company: A & B\n";
+        assert_eq!(
+            super::parse(yaml).unwrap(),
+            json!({"title": "*Emphasis* & more", "tera_context": {"company": "A & B"}})
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let yaml = "title: \"42\"\nengine: pdflatex\ndo_tera: True\nnumber: 6\nfloat: 2.50\n\
+                    empty:\nquoted: 'True'\nignored_: foo\ncontext:\n  list:\n    - 1\n    - Eggs\n";
+        assert_eq!(
+            super::parse(yaml).unwrap(),
+            json!({
+                "title": "42",
+                "engine": "pdflatex",
+                "do_tera": true,
+                "number": "6",
+                "float": "2.5",
+                "empty": "",
+                "quoted": "True",
+                "context": {"list": ["1", "Eggs"]},
+            })
+        );
+    }
+}
@@ -0,0 +1,310 @@
+/// This module explains failed PDF conversions. The first error is taken from the log of the
+/// LaTeX engine and the line of the generated LaTeX it refers to is mapped back to the markdown
+/// source by comparing the words of both lines. The result is approximate but points the writer
+/// to the right paragraph instead of a line of a LaTeX file they never see.
+use crate::process::{self, CancelHandle};
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use regex::Regex;
+
+/// The LaTeX engines whose log can be analyzed.
+const ENGINES: [&str; 3] = ["pdflatex", "xelatex", "lualatex"];
+
+/// Matches the words and control sequences (ex.: `\foo`) used to compare a line of the generated
+/// LaTeX with the lines of the markdown source.
+const WORD_PATTERN: &str = r"\\[A-Za-z@]+|[^\W_]{3,}";
+
+/// Matches the line of the log stating the line of the LaTeX file in which the error occurred
+/// (ex.: `l.42 \foo`).
+const LOG_LINE_PATTERN: &str = r"^l\.(\d+)";
+
+/// Number of lines of the LaTeX file looked at, starting at the line stated in the log and going
+/// backwards. LaTeX often reports an error at the end of the paragraph containing it.
+const LOOK_BEHIND: usize = 5;
+
+/// The first error reported by the LaTeX engine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatexError {
+    /// The message of the error (ex.: `Undefined control sequence.`).
+    pub message: String,
+    /// Line of the LaTeX file in which the error occurred, if stated.
+    pub line: Option<usize>,
+}
+
+/// Position within a markdown file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    /// Path of the markdown file, either the document or a file pulled in by Tera.
+    pub path: PathBuf,
+    /// Line within the file.
+    pub line: usize,
+    /// Content of the line.
+    pub text: String,
+    /// Line of the document which pulls in the file, `None` if the location is within the
+    /// document itself or the line couldn't be determined.
+    pub included_at: Option<usize>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {} of {}", self.line, self.path.display())?;
+        if let Some(x) = self.included_at {
+            write!(f, " (included at line {} of the document)", x)?;
+        }
+        write!(f, " `{}`", self.text.trim())
+    }
+}
+
+/// Explanation of a failed PDF conversion shown instead of the output of the LaTeX engine.
+pub struct Report {
+    /// The first error of the log.
+    pub error: LatexError,
+    /// Approximate position of the error within the markdown source, if found.
+    pub location: Option<Location>,
+    /// Folder containing the generated LaTeX file and the log of the engine.
+    pub folder: PathBuf,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LaTeX error \"{}\"", self.error.message)?;
+        match (&self.location, self.error.line) {
+            (Some(x), _) => write!(f, " near {}", x)?,
+            (None, Some(x)) => write!(f, " at line {} of the generated LaTeX", x)?,
+            (None, None) => {}
+        }
+        write!(
+            f,
+            "; the generated LaTeX and the log of the engine are kept in {}",
+            self.folder.display()
+        )
+    }
+}
+
+/// The texts the input of pandoc originates from. Used to map a line of the generated LaTeX back
+/// to the markdown source, including the Tera rendering step.
+pub struct SourceMap {
+    /// Path of the markdown document.
+    path: PathBuf,
+    /// Content of the document as rendered by Tera (the same as the source if Tera isn't used).
+    rendered: String,
+    /// Number of lines of the rendered content before the body (the front matter).
+    offset: usize,
+    /// The files which may contain the rendered lines, the document first followed by the files
+    /// pulled in by Tera. Empty if Tera isn't used.
+    files: Vec<(PathBuf, String)>,
+}
+
+impl SourceMap {
+    /// Returns the map for the given document. The body has to be a part at the end of the
+    /// rendered content. The files pulled in by Tera are only given if Tera was applied, files
+    /// which can't be read are skipped.
+    pub fn new(
+        path: PathBuf,
+        source: String,
+        rendered: &str,
+        body: &str,
+        tera_files: Option<Vec<PathBuf>>,
+    ) -> Self {
+        let offset = rendered[..rendered.len() - body.len()].matches('\n').count();
+        let files = match tera_files {
+            Some(x) => std::iter::once((path.clone(), source))
+                .chain(
+                    x.into_iter()
+                        .filter_map(|y| fs::read_to_string(&y).ok().map(|z| (y, z))),
+                )
+                .collect(),
+            None => Vec::new(),
+        };
+        Self {
+            path,
+            rendered: String::from(rendered),
+            offset,
+            files,
+        }
+    }
+
+    /// Returns the location in the markdown source which matches the given line of the generated
+    /// LaTeX best. `None` if no line of the body shares a word with it.
+    pub fn locate(&self, latex: &str) -> Option<Location> {
+        let body: Vec<&str> = self.rendered.lines().skip(self.offset).collect();
+        let (index, _) = best_match(latex, &body)?;
+        self.body_line(index)
+    }
+
+    /// Returns the location in the markdown source of the line of the body with the given index
+    /// (starting at 0). `None` if the body has no such line or it can't be found in the source.
+    pub fn body_line(&self, index: usize) -> Option<Location> {
+        let line = self.offset + index + 1;
+        let text = self.rendered.lines().nth(line - 1)?;
+        if self.files.is_empty() {
+            return Some(Location {
+                path: self.path.clone(),
+                line,
+                text: String::from(text),
+                included_at: None,
+            });
+        }
+
+        // Tera changes the lines, thus the rendered line is looked up in the document and the
+        // files it pulls in. On a tie the document wins.
+        let mut found: Option<(&PathBuf, usize, &str, usize)> = None;
+        for (path, content) in &self.files {
+            let lines: Vec<&str> = content.lines().collect();
+            if let Some((x, score)) = best_match(text, &lines) {
+                if found.map(|y| score > y.3).unwrap_or(true) {
+                    found = Some((path, x + 1, lines[x], score));
+                }
+            }
+        }
+        let (path, line, text, _) = found?;
+        let included_at = match path == &self.path {
+            true => None,
+            false => self.include_line(path),
+        };
+        Some(Location {
+            path: path.clone(),
+            line,
+            text: String::from(text),
+            included_at,
+        })
+    }
+
+    /// Returns the line of the document containing the Tera tag which pulls in the given file.
+    fn include_line(&self, file: &Path) -> Option<usize> {
+        let name = file.file_name()?.to_str()?;
+        let (_, source) = self.files.first()?;
+        source
+            .lines()
+            .position(|x| x.contains("{%") && x.contains(name))
+            .map(|x| x + 1)
+    }
+}
+
+/// Whether the given engine is a LaTeX engine whose log can be analyzed. The engine can be given
+/// as a name or a path.
+pub fn is_latex(engine: &str) -> bool {
+    Path::new(engine)
+        .file_stem()
+        .and_then(|x| x.to_str())
+        .map(|x| ENGINES.contains(&x))
+        .unwrap_or(false)
+}
+
+/// Runs the LaTeX engine on the given file within it's folder and returns the content of the
+/// resulting log file. The engine stops at the first error. Files referenced relative to the
+/// document (ex.: images) are found through the given resource folder. Returns `None` if the
+/// engine couldn't be run or didn't write a log.
+pub fn compile(
+    engine: &str,
+    tex: &Path,
+    resource_path: &Path,
+    timeout: Option<Duration>,
+    cancel: &CancelHandle,
+) -> Option<String> {
+    let folder = tex.parent()?;
+    let mut cmd = Command::new(engine);
+    cmd.arg("-interaction=nonstopmode")
+        .arg("-halt-on-error")
+        .arg(tex.file_name()?)
+        .current_dir(folder);
+    // The empty element keeps the default search path of the TeX distribution.
+    if let Ok(x) = env::join_paths([resource_path, Path::new("")]) {
+        cmd.env("TEXINPUTS", x);
+    }
+    if process::output(&mut cmd, timeout, cancel).is_err() {
+        debug!("couldn't run {} to analyze the failed conversion", engine);
+        return None;
+    }
+    fs::read_to_string(tex.with_extension("log")).ok()
+}
+
+/// Returns the first error of a log of the LaTeX engine. Errors start with a `!`, the line of the
+/// LaTeX file follows a few lines later (ex.: `l.42 \foo`). Pandoc prints the same lines when the
+/// conversion into a PDF fails.
+pub fn first_error(log: &str) -> Option<LatexError> {
+    let re = Regex::new(LOG_LINE_PATTERN).unwrap();
+    let mut lines = log.lines().skip_while(|x| !x.starts_with("! "));
+    let message = lines.next()?[2..].trim();
+    let line = lines
+        .take_while(|x| !x.starts_with("! "))
+        .find_map(|x| re.captures(x).and_then(|y| y[1].parse().ok()));
+    Some(LatexError {
+        message: String::from(message),
+        line,
+    })
+}
+
+/// Returns the index of the line of the LaTeX content the error refers to which matches the
+/// markdown source best. Goes back a few lines if the stated line doesn't match at all (ex.:
+/// `\end{itemize}`).
+pub fn locate(error: &LatexError, latex: &str, sources: &SourceMap) -> Option<Location> {
+    let lines: Vec<&str> = latex.lines().collect();
+    let line = error.line?.min(lines.len());
+    (line.saturating_sub(LOOK_BEHIND).max(1)..=line)
+        .rev()
+        .find_map(|x| sources.locate(lines[x - 1]))
+}
+
+/// Returns the index of the line sharing the most words with the given text together with the
+/// number of shared words. `None` if no line shares a word.
+fn best_match(text: &str, lines: &[&str]) -> Option<(usize, usize)> {
+    let re = Regex::new(WORD_PATTERN).unwrap();
+    let mut words: Vec<String> = re
+        .find_iter(text)
+        .map(|x| x.as_str().to_lowercase())
+        .collect();
+    words.sort();
+    words.dedup();
+    let mut rsl = None;
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.to_lowercase();
+        let score = words.iter().filter(|x| line.contains(x.as_str())).count();
+        if score > rsl.map(|(_, x)| x).unwrap_or(0) {
+            rsl = Some((i, score));
+        }
+    }
+    rsl
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SourceMap;
+
+    use std::path::PathBuf;
+
+    #[test]
+    fn locate() {
+        let log = "This is XeTeX\n\
+                   ! Undefined control sequence.\n\
+                   l.4 The \\foo\n\
+                   \x20            value of the setting\n\
+                   ! Emergency stop.\n";
+        let error = super::first_error(log).unwrap();
+        assert_eq!(error.message, "Undefined control sequence.");
+        assert_eq!(error.line, Some(4));
+
+        let latex = "\\begin{document}\n\
+                     \\section{Introduction}\n\
+                     Some text.\n\
+                     The \\foo value of the setting\n";
+        let source = "---\ntitle: Test\n---\n# Introduction\n\nSome text.\n\nThe \\foo value\n";
+        let sources = SourceMap::new(
+            PathBuf::from("/doc.md"),
+            String::from(source),
+            source,
+            &source[20..],
+            None,
+        );
+        let location = super::locate(&error, latex, &sources).unwrap();
+        assert_eq!(location.path, PathBuf::from("/doc.md"));
+        assert_eq!(location.line, 8);
+        assert_eq!(location.included_at, None);
+    }
+}
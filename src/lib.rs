@@ -1,20 +1,105 @@
 #[macro_use]
 extern crate log;
 
+mod ast;
+mod batch;
+mod bundle;
+mod cache;
+mod config;
 mod error;
 mod example;
 mod file;
+mod filter;
+mod frontmatter;
+mod latex;
 mod libreoffice;
 mod metadata;
 mod pandoc;
+mod process;
+mod project;
+mod registry;
 mod tera;
 mod util;
+mod validate;
+mod version;
+mod warning;
+mod watch;
 
+pub use ast::{walk, Filter};
+pub use batch::Report;
+pub use cache::CacheMode;
+pub use process::CancelHandle;
+
+use batch::Batch;
+use config::Config;
 use example::Example;
 use file::File;
+use metadata::Layers;
+use project::Project;
+use registry::TemplateRegistry;
+use watch::Watch;
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::value::Value;
 
+/// Additional settings for a conversion which don't originate from the document itself. Use
+/// `Options::default()` for a conversion without any additional settings.
+#[derive(Clone, Default)]
+pub struct Options {
+    /// Whether the finalized pandoc input should be printed to stdout for debugging purposes.
+    pub output_raw: bool,
+    /// Optional header values applied to the document. The values of the document itself take
+    /// priority over these defaults.
+    pub defaults: Option<Value>,
+    /// Paths to data files (YAML, JSON, TOML or CSV) loaded into the Tera context. Relative
+    /// paths are resolved relative to the current working directory. These files are loaded
+    /// after the files stated in the `tera_context_files` field of the header.
+    pub context_files: Vec<String>,
+    /// Whether misspelled and deprecated header fields should result in an error instead of a
+    /// warning.
+    pub strict: bool,
+    /// Whether warnings reported by pandoc (ex.: a citation missing in the bibliography) should
+    /// fail the conversion. The output file is removed in this case.
+    pub deny_warnings: bool,
+    /// Name of the profile to apply. The values of the profile's block in the `profiles` field
+    /// override all other header values.
+    pub profile: Option<String>,
+    /// Header values given as `key=value` assignments (ex.: `tera_context.customer=ACME`). They
+    /// override all other header values, relative paths are resolved relative to the current
+    /// working directory.
+    pub overrides: Vec<String>,
+    /// How files referenced by URL (ex.: a template) are taken from the download cache.
+    pub cache_mode: CacheMode,
+    /// Filters implemented in Rust which transform the document (as pandoc's JSON AST) before
+    /// the conversion into the output formats. Applied in the given order, before the filters of
+    /// the `filters` header field.
+    pub filters: Vec<Arc<dyn Filter>>,
+    /// Maximal time pandoc and LibreOffice may run for the conversion into one output format,
+    /// takes priority over the `timeout` field of the header. The tools are stopped afterwards.
+    pub timeout: Option<Duration>,
+    /// Handle to cancel the conversion from another thread. Running external tools are stopped
+    /// and the temporary files removed.
+    pub cancel: CancelHandle,
+    /// Configuration of the current user. Loaded by the functions of this library, thus there is
+    /// no need to set it.
+    pub config: Config,
+}
+
+impl<'a> Options {
+    /// Loads the configuration of the current user into the options.
+    fn with_config(self) -> Result<Self, error::SmoothError<'a>> {
+        Ok(Self {
+            config: Config::load()?,
+            ..self
+        })
+    }
+}
 
 /// Defines the possible output formats for rsmooth.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum OutputFormat {
     /// Portable Document Format.
     Pdf,
@@ -28,20 +113,189 @@ pub enum OutputFormat {
     Reveal,
 }
 
+impl OutputFormat {
+    /// Returns the name of the output format as used on the command line and in project files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Odt => "odt",
+            OutputFormat::Docx => "docx",
+            OutputFormat::OdtPdf => "odtpdf",
+            OutputFormat::Reveal => "reveal",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    /// Parses the name of an output format as used on the command line and in project files.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "pdf" => Ok(OutputFormat::Pdf),
+            "odt" => Ok(OutputFormat::Odt),
+            "docx" => Ok(OutputFormat::Docx),
+            "odtpdf" => Ok(OutputFormat::OdtPdf),
+            "reveal" => Ok(OutputFormat::Reveal),
+            _ => Err(String::from(name)),
+        }
+    }
+}
+
 /// Converts a given markdown file and saves the result to the same path with the same file name.
-/// The keep_temp parameter states whether the temporary pandoc input file should be kept for
-/// debugging purposes.
+/// The options contain additional settings for the conversion (ex.: whether the prepared pandoc
+/// input should be printed for debugging purposes). The document is converted into all given
+/// formats, the header and the Tera templating are only processed once. If no format is given,
+/// the formats stated in the `format` field of the header are used (defaulting to PDF). When
+/// converting into multiple formats the extension of the output path is replaced for every
+/// format.
 pub fn convert<'a>(
     path: &'a str,
     output: Option<&'a str>,
-    keep_temp: bool,
-    format: OutputFormat,
+    formats: Vec<OutputFormat>,
+    options: Options,
 ) -> Result<(), error::SmoothError<'a>> {
-    let f = File::new(path, output, format)?;
-    f.convert(keep_temp)?;
+    let f = File::new(path, output, formats, options.with_config()?)?;
+    f.convert()?;
     Ok(())
 }
 
+/// Converts multiple documents at once. Inputs can be paths to markdown files, folders (all
+/// markdown files within will be converted) or glob patterns. Up to `jobs` documents are
+/// converted at the same time, the output files are saved next to their input files. A failing
+/// document won't stop the others, the returned report states the outcome for every document.
+pub fn convert_batch<'a>(
+    inputs: Vec<&'a str>,
+    jobs: usize,
+    formats: Vec<OutputFormat>,
+    options: Options,
+) -> Result<Report, error::SmoothError<'a>> {
+    Ok(Batch::new(inputs, jobs, formats, options.with_config()?)?.run())
+}
+
+/// Builds all documents of a project as described in it's project file (`rsmooth.yaml`,
+/// `rsmooth.yml` or `rsmooth.toml`). If no path to the project file is given, the file is searched
+/// in the current working directory. The default header values of the project file are applied
+/// to every document. Up to `jobs` documents are converted at the same time.
+pub fn build<'a>(
+    project: Option<&'a str>,
+    jobs: usize,
+    options: Options,
+) -> Result<Report, error::SmoothError<'a>> {
+    let project = Project::from(project)?;
+    Ok(Batch::from_project(project, jobs, options.with_config()?).run())
+}
+
+/// Watches a given markdown file and converts it every time the file or one of the files it
+/// depends on (Tera includes, template, reference, bibliography and citation style) changes.
+/// Errors occurring while a conversion are reported but won't stop the watching. This function
+/// only returns when the watching itself fails.
+pub fn watch<'a>(
+    path: &'a str,
+    output: Option<&'a str>,
+    formats: Vec<OutputFormat>,
+    options: Options,
+) -> Result<(), error::SmoothError<'a>> {
+    Watch::new(path, output, formats, options.with_config()?).run()
+}
+
+/// Describes the effective configuration. Lists the executables of the external tools, the
+/// template folders and the default header values of the user configuration file together with
+/// their origin. If the path to a document is given, the header values of the document (with
+/// all extended files) are included. The profile and the overrides of the options are applied on
+/// top.
+pub fn config<'a>(path: Option<&'a str>, options: Options) -> Result<String, error::SmoothError<'a>> {
+    let config = Config::load()?;
+    let mut rsl = String::new();
+    rsl.push_str(&format!(
+        "# configuration file: {}\n",
+        match config.path {
+            Some(ref x) => x.display().to_string(),
+            None => String::from("<none>"),
+        }
+    ));
+    for (name, value, source) in config.executables() {
+        rsl.push_str(&format!("{}: {}  # {}\n", name, Value::from(value), source));
+    }
+    for (name, folder) in config.templates.iter() {
+        rsl.push_str(&format!(
+            "templates.{}: {}  # user config\n",
+            name,
+            Value::from(folder.to_string_lossy())
+        ));
+    }
+    let (mut layers, wd) = match path {
+        Some(x) => {
+            let file = util::normalize_path(x, None)?;
+            if !file.exists() {
+                return Err(error::SmoothError::InputFileNotFound(x, file));
+            }
+            let wd = match file.parent() {
+                Some(y) => y.to_path_buf(),
+                None => return Err(error::SmoothError::NoParentFolder(file)),
+            };
+            (Layers::read(&file, &config, None)?, wd)
+        }
+        None => (Layers::read_config(&config)?, current_dir()?),
+    };
+    if let Some(ref x) = options.profile {
+        layers.select_profile(x)?;
+    }
+    layers.set_overrides(&options.overrides, &config)?;
+    layers.apply_bundle(&wd, &TemplateRegistry::new(&wd, &config))?;
+    for (key, value, source) in layers.sources() {
+        rsl.push_str(&format!("{}: {}  # {}\n", key, value, source));
+    }
+    Ok(rsl)
+}
+
+/// Lists the templates which can be used by name (ex.: `template: letter`) together with the
+/// folder they originate from. The templates are ordered by priority, templates shadowed by one
+/// with the same name in a folder of higher priority are marked. If the path to a document is
+/// given, the templates available to this document are listed, otherwise the ones available in
+/// the current working directory.
+pub fn templates<'a>(path: Option<&'a str>) -> Result<String, error::SmoothError<'a>> {
+    let folder = match path {
+        Some(x) => {
+            let file = util::normalize_path(x, None)?;
+            if !file.exists() {
+                return Err(error::SmoothError::InputFileNotFound(x, file));
+            }
+            match file.parent() {
+                Some(y) => y.to_path_buf(),
+                None => return Err(error::SmoothError::NoParentFolder(file)),
+            }
+        }
+        None => current_dir()?,
+    };
+    let registry = TemplateRegistry::new(&folder, &Config::load()?);
+    let mut rsl = String::new();
+    for (folder, source) in registry.folders() {
+        rsl.push_str(&format!("# {}: {}\n", source, folder.display()));
+    }
+    for (name, file, _, shadowed) in registry.list() {
+        let notes: Vec<&str> = vec![(file.is_dir(), "bundle"), (shadowed, "shadowed")]
+            .into_iter()
+            .filter(|(x, _)| *x)
+            .map(|(_, x)| x)
+            .collect();
+        rsl.push_str(&format!("{}: {}", name, file.display()));
+        if !notes.is_empty() {
+            rsl.push_str(&format!("  # {}", notes.join(", ")));
+        }
+        rsl.push('\n');
+    }
+    Ok(rsl)
+}
+
+/// Returns the current working directory.
+fn current_dir<'a>() -> Result<std::path::PathBuf, error::SmoothError<'a>> {
+    match std::env::current_dir() {
+        Ok(x) => Ok(x),
+        Err(_) => Err(util::NormalizeError::WdNotFound.into()),
+    }
+}
+
 /// Provides a example markdown document showcasing the key concepts of rsmooth. If no path is
 /// given, the method will return the document as a string otherwise the content will be saved
 /// to the given path.
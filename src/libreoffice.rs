@@ -1,17 +1,19 @@
 /// The module handles the calls to Libre-Office.
+use crate::process::{self, CancelHandle, ProcessError};
+
 use std::fmt;
 use std::io::{Error as IOError, ErrorKind};
-use std::env;
 use std::path::PathBuf;
 use std::process::Command;
+use std::time::Duration;
 
 /// Default name of the Libre-Office executable. Will be used when no other name is defined
 /// via the `LIBREOFFICE_CMD` constant of this module.
-const LIBREOFFICE_CMD: &str = "soffice";
+pub const LIBREOFFICE_CMD: &str = "soffice";
 
 /// Name of the environment variable which will be used to determine the name of the Libre-Office
 /// executable.
-const LIBREOFFICE_ENV: &str = "LIBREOFFICE_CMD";
+pub const LIBREOFFICE_ENV: &str = "LIBREOFFICE_CMD";
 
 /// Different errors occurring while the execution of Libre-Office.
 pub enum LibreOfficeError {
@@ -22,6 +24,10 @@ pub enum LibreOfficeError {
     ExecutionFailed(PathBuf, PathBuf, String),
     /// The executable was found but calling failed.
     CallFailed(IOError),
+    /// Libre-Office didn't finish within the timeout and was killed. Contains the timeout.
+    ExecutionTimeout(Duration),
+    /// Libre-Office was killed as the conversion was cancelled.
+    Cancelled,
 }
 
 impl fmt::Display for LibreOfficeError {
@@ -30,13 +36,13 @@ impl fmt::Display for LibreOfficeError {
             LibreOfficeError::NotFound(executable) => match executable == &LIBREOFFICE_CMD {
                 true => write!(
                     f, 
-                    "couldn't find \"{}\" on your system, use the env \"{}\" to define a non default executable name",
+                    "couldn't find \"{}\" on your system, use the env \"{}\" or the libreoffice field of the configuration file to define a non default executable name",
                     LIBREOFFICE_CMD,
                     LIBREOFFICE_ENV,
                 ),
                 false => write!(
                     f,
-                    "couldn't find libreoffice with the executable name \"{}\" use env \"{}\" to specify otherwise",
+                    "couldn't find libreoffice with the executable name \"{}\" use env \"{}\" or the configuration file to specify otherwise",
                     executable,
                     LIBREOFFICE_ENV,
                 ),
@@ -54,28 +60,36 @@ impl fmt::Display for LibreOfficeError {
                 "couldn't call libreoffice {}",
                 err,
             ),
+            LibreOfficeError::ExecutionTimeout(timeout) => write!(
+                f,
+                "libreoffice didn't finish within {} seconds and was stopped",
+                timeout.as_secs_f64()
+            ),
+            LibreOfficeError::Cancelled => write!(
+                f,
+                "libreoffice was stopped as the conversion was cancelled"
+            ),
         }
     }
 }
 
-/// Wrapps Libre-Office.
-pub struct LibreOffice(String);
+/// Wrapps Libre-Office. Contains the executable and the handle to cancel the running calls.
+pub struct LibreOffice(String, CancelHandle);
 
 impl LibreOffice {
-    /// Returns a new instance of the LibreOffice struct. Determines the name of the executable
-    /// based on the content of the LIBREOFFICE_ENV environment variable, defaults to
-    /// LIBREOFFICE_CMD.
-    pub fn new() -> Self {
-        Self(match env::var(LIBREOFFICE_ENV) {
-            Ok(x) => x,
-            Err(_) => String::from(LIBREOFFICE_CMD),
-        })
+    /// Returns a new instance of the LibreOffice struct using the given executable. The executable
+    /// is determined by the user configuration (see `Config::libreoffice`). The calls are stopped
+    /// when the given handle gets cancelled.
+    pub fn new(executable: String, cancel: CancelHandle) -> Self {
+        Self(executable, cancel)
     }
 
-    /// Calls the actual conversion from a office document into a PDF file.
+    /// Calls the actual conversion from a office document into a PDF file. Libre-Office is
+    /// stopped when it runs longer than the timeout.
     pub fn convert_to_pdf(
         &self,
         input: &PathBuf,
+        timeout: Option<Duration>,
     ) -> Result<(), LibreOfficeError> {
         let mut cmd = Command::new(self.0.clone());
         cmd.arg("--headless")
@@ -83,7 +97,7 @@ impl LibreOffice {
             .arg("pdf:writer_pdf_Export")
             .arg("-env:UserInstallation=file:///tmp/LibreOffice_Conversion_${USER}")
             .arg(input);
-        match cmd.output() {
+        match process::output(&mut cmd, timeout, &self.1) {
             Ok(x) => {
                 if x.status.success() {
                     Ok(())
@@ -91,13 +105,15 @@ impl LibreOffice {
                     Err(LibreOfficeError::ExecutionFailed(input.to_path_buf(), PathBuf::from("ho"), String::from_utf8(x.stderr).unwrap()))
                 }
             }
-            Err(e) => {
+            Err(ProcessError::Io(e)) => {
                 if let ErrorKind::NotFound = e.kind() {
                     Err(LibreOfficeError::NotFound(self.0.clone()))
                 } else {
                     Err(LibreOfficeError::CallFailed(e))
                 }
             }
+            Err(ProcessError::Timeout(x)) => Err(LibreOfficeError::ExecutionTimeout(x)),
+            Err(ProcessError::Cancelled) => Err(LibreOfficeError::Cancelled),
         }
     }
 }
@@ -1,27 +1,27 @@
+use crate::bundle::Bundle;
+use crate::config::Config;
 use crate::error::SmoothError;
-use crate::pandoc::Pandoc;
-use crate::util;
+use crate::filter::{LuaFilter, PandocFilter};
+use crate::frontmatter;
+use crate::registry::TemplateRegistry;
+use crate::util::{self, OneOrMany};
 use crate::OutputFormat;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::fmt;
 use std::fs;
-use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use serde::Deserialize;
 use serde_json;
-use serde_json::value::Value;
-
-/// Name of the temporary pandoc template for extracting the header of a markdown file as JSON.
-const JSON_TEMPLATE_PATH: &str = "rsmooth-metadata.pandoc-tpl";
-
-/// Content of the metadata as JSON template.
-const JSON_TEMPLATE: &str = "$meta-json$";
+use serde_json::value::{Map, Value};
 
 /// Defines the metadata header of a rsmooth markdown file.
-#[derive(Debug, Deserialize)]
-struct Header {
+#[derive(Debug, Clone, Deserialize)]
+pub struct Header {
     /// Path to the pandoc template file can be absolute or relative to the markdown file. Tilde
     /// (`~`) can be used to refer to the home folder of the current user. It's also possible to
     /// use to use environment variables by prefixing the name with a dollar sign (ex.: `$PATH`).
@@ -31,22 +31,58 @@ struct Header {
     /// LaTeX engine to be used. Defaults to xelatex.
     #[serde(default = "default_engine")]
     engine: String,
-    /// Set additional parameters to pandoc.
-    // #[serde(default = "default_pandoc_options")]
-    pandoc_optons: Option<String>,
+    /// Set additional parameters to pandoc. A string is split into arguments like a shell does,
+    /// the elements of a list are passed as they are. The misspelled name `pandoc_optons` of
+    /// earlier versions is still accepted.
+    #[serde(alias = "pandoc_optons")]
+    pandoc_options: Option<OneOrMany<String>>,
+    /// Whether a table of contents should be included.
+    #[serde(default)]
+    toc: bool,
+    /// Number of section levels included in the table of contents.
+    toc_depth: Option<Value>,
+    /// Whether the sections should be numbered.
+    #[serde(default)]
+    number_sections: bool,
+    /// Name of the style (or path to a theme file) used for the syntax highlighting of code.
+    highlight_style: Option<String>,
+    /// Language of the document as an IETF language tag (ex.: `en-US`).
+    lang: Option<String>,
+    /// Paths to files included at the end of the header (ex.: LaTeX preamble).
+    include_in_header: Option<OneOrMany<String>>,
+    /// Paths to files included at the beginning of the document body.
+    include_before_body: Option<OneOrMany<String>>,
+    /// Template variables passed to pandoc.
+    variables: Option<Map<String, Value>>,
+    /// Paths to additional YAML files pandoc reads metadata from.
+    metadata_files: Option<OneOrMany<String>>,
     /// Whether templating with the Tera engine should be executed on the input file or not.
     #[serde(default = "default_do_tera")]
-    do_tera: bool,
+    pub do_tera: bool,
     /// Optional template context aka. variables etc.
     pub tera_context: Option<HashMap<String, Value>>,
+    /// Paths to data files (YAML, JSON, TOML or CSV) loaded into the Tera context.
+    tera_context_files: Option<OneOrMany<String>>,
     /// Whether newline should break text in description texts. This is especially useful when
     /// using description lists for screen- and stageplays.
     #[serde(default = "default_break_description")]
     break_description: bool,
-    /// Path to bibliography file (JSON CTL).
-    bibliography: Option<String>,
-    /// Optional path to the Citation Style Language file, altering the citation style.
-    pub csl: Option<String>,
+    /// Filter(s) applied to the document in the given order. An entry is the name of a filter of
+    /// the embedded filter library (ex.: `pagebreak`), `citeproc`, the path to a Lua filter
+    /// (`.lua`) or the path (or name) of a JSON filter executable.
+    filters: Option<OneOrMany<String>>,
+    /// Path(s) to bibliography files (ex.: BibTeX or CSL JSON).
+    bibliography: Option<OneOrMany<String>>,
+    /// Optional path to the Citation Style Language file, altering the citation style. Can also
+    /// be the name of a style (ex.: `apa`) which is looked up in the style folders.
+    csl: Option<String>,
+    /// Output format(s) used when no format is given on the command line.
+    format: Option<OneOrMany<String>>,
+    /// Version(s) of pandoc the document requires (ex.: `">=3.1"` or `">=2.11, <4"`).
+    pub pandoc_version: Option<String>,
+    /// Maximal time in seconds pandoc (and LibreOffice) may run for the conversion into one
+    /// output format.
+    timeout: Option<Value>,
 }
 
 /// Returns the default value (xelatex) for the engine field. Used, when the field is not set in
@@ -67,68 +103,611 @@ fn default_break_description() -> bool {
     false
 }
 
-impl<'a> Header {
-    /// Tries to read the YAML header of a given input file to a Metadata struct using pandoc.
-    fn from(file: &PathBuf) -> Result<Self, SmoothError<'a>> {
-        let json_tpl = Header::create_template()?;
-        let raw = match Pandoc::new().convert_with_template_to_str(file, json_tpl.clone()) {
+/// Empty fields in the front matter are read as empty strings (as pandoc does). This function
+/// treats them as not set.
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|x| !x.trim().is_empty())
+}
+
+/// Header fields containing paths which are resolved by rsmooth.
+const PATH_FIELDS: [&str; 12] = [
+    "template",
+    "template_bundle",
+    "reference",
+    "bibliography",
+    "csl",
+    "highlight_style",
+    "extends",
+    "tera_context_files",
+    "include_in_header",
+    "include_before_body",
+    "metadata_files",
+    "filters",
+];
+
+/// Header fields containing blocks of header values (ex.: `formats.pdf`). The path fields of these
+/// blocks are resolved the same way as the top-level ones.
+const BLOCK_FIELDS: [&str; 2] = ["formats", "profiles"];
+
+/// Returns the values of all path fields of a header including the ones in the blocks of the
+/// `formats` and `profiles` fields. Lists (ex.: multiple bibliography files) are returned element
+/// by element.
+fn path_values(header: &mut Value) -> Vec<&mut Value> {
+    let fields = match header.as_object_mut() {
+        Some(x) => x,
+        None => return vec![],
+    };
+    let mut rsl = Vec::new();
+    for (key, value) in fields.iter_mut() {
+        if BLOCK_FIELDS.contains(&key.as_str()) {
+            if let Value::Object(x) = value {
+                for block in x.values_mut() {
+                    rsl.extend(path_values(block));
+                }
+            }
+            continue;
+        }
+        if !PATH_FIELDS.contains(&key.as_str()) {
+            continue;
+        }
+        match value {
+            Value::Array(x) => rsl.extend(x.iter_mut()),
+            x => rsl.push(x),
+        }
+    }
+    rsl
+}
+
+/// Resolves the relative paths in the path fields of a header which doesn't originate from the
+/// document itself (ex.: the defaults of a project manifest) relative to the given folder. A path
+/// is only replaced when the file exists or the value explicitly is a path (contains a path
+/// separator), all other values are left untouched and resolved relative to the document later.
+/// Paths referring to a named template folder (`@name/file`) and URLs are also left untouched.
+pub fn resolve_paths(header: &mut Value, wd: &PathBuf) {
+    for value in path_values(header) {
+        let path = match value.as_str() {
+            Some(x) if !x.starts_with('@') && !util::is_url(x) => String::from(x),
+            _ => continue,
+        };
+        let resolved = match util::normalize_path(path.as_str(), Some(wd)) {
             Ok(x) => x,
-            Err(x) => {
-                Header::remove_template(json_tpl.clone())?;
-                return Err(SmoothError::Pandoc(x));
+            Err(_) => continue,
+        };
+        if resolved.exists() || path.contains(std::path::MAIN_SEPARATOR) {
+            if let Some(x) = resolved.to_str() {
+                *value = Value::from(x);
             }
+        }
+    }
+}
+
+/// Resolves the relative paths in the path fields of values set on the command line relative to
+/// the given folder (the current working directory), whether the files exist or not. Left
+/// untouched are references to named template folders (`@name/file`), URLs, the filters of the
+/// library, `citeproc` and plain names without an extension which aren't files in the folder, as
+/// these name a template, citation style or filter executable.
+pub fn resolve_override_paths(header: &mut Value, wd: &PathBuf) {
+    for value in path_values(header) {
+        let path = match value.as_str() {
+            Some(x) if !x.starts_with('@') && !util::is_url(x) => String::from(x),
+            _ => continue,
         };
-        let data: Self = match serde_json::from_str(&raw) {
+        if path == "citeproc" || LuaFilter::is_library(&path) {
+            continue;
+        }
+        let resolved = match util::normalize_path(path.as_str(), Some(wd)) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        let name = !path.contains(&['/', '\\'][..]) && Path::new(&path).extension().is_none();
+        if name && !resolved.exists() {
+            continue;
+        }
+        if let Some(x) = resolved.to_str() {
+            *value = Value::from(x);
+        }
+    }
+}
+
+/// Returns the paths stated in the path fields of a header, whether the files exist or not.
+/// Relative paths are resolved relative to the given folder. Paths referring to a named template
+/// folder (`@name/file`) and URLs are skipped.
+pub fn referenced_paths(header: &Value, wd: &PathBuf) -> Vec<PathBuf> {
+    let mut header = header.clone();
+    path_values(&mut header)
+        .into_iter()
+        .filter_map(|x| x.as_str().map(String::from))
+        .filter(|x| !x.starts_with('@') && !util::is_url(x))
+        .filter_map(|x| util::normalize_path(x, Some(wd)).ok())
+        // Removes `.` components (ex.: `./template.tex`) to match the paths of file events.
+        .map(|x| x.components().collect())
+        .collect()
+}
+
+/// Replaces the references to named template folders (`@name/file`) in the path fields of a
+/// header with the path of the folder as configured by the user.
+fn expand_template_folders<'a>(header: &mut Value, config: &Config) -> Result<(), SmoothError<'a>> {
+    for value in path_values(header) {
+        if let Some(x) = value.as_str() {
+            *value = Value::from(config.expand_path(x)?);
+        }
+    }
+    Ok(())
+}
+
+/// Origin of a set of header values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// The defaults of the user configuration file.
+    Config(PathBuf),
+    /// Default values given by the caller (ex.: the defaults of a project file).
+    Defaults,
+    /// A YAML file pulled in by the `extends` field.
+    Extends(PathBuf),
+    /// The front matter of the document itself.
+    Document,
+    /// The block of the selected profile in the `profiles` field. Contains the name of the
+    /// profile.
+    Profile(String),
+    /// Values set on the command line (`--set key=value`).
+    CommandLine,
+    /// The manifest of the template bundle stated in the `template_bundle` field.
+    Bundle(PathBuf),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Config(path) => write!(f, "user config {}", path.display()),
+            Source::Defaults => write!(f, "project defaults"),
+            Source::Extends(path) => write!(f, "extends {}", path.display()),
+            Source::Document => write!(f, "document"),
+            Source::Profile(name) => write!(f, "profile {}", name),
+            Source::CommandLine => write!(f, "command line"),
+            Source::Bundle(path) => write!(f, "bundle {}", path.display()),
+        }
+    }
+}
+
+/// A set of header values from one source.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    /// Where the values originate from.
+    pub source: Source,
+    /// The header values, always a mapping.
+    pub values: Value,
+}
+
+/// All header values applying to a document. The layers are ordered by priority, values of a
+/// later layer override the values of the earlier ones. Mappings are merged recursively while
+/// lists and all other values are replaced as a whole.
+#[derive(Debug, Clone)]
+pub struct Layers {
+    layers: Vec<Layer>,
+    /// The applied template bundle.
+    bundle: Option<Bundle>,
+}
+
+impl<'a> Layers {
+    /// Collects the header values of the given document. The defaults of the user configuration
+    /// form the lowest layer followed by the optional defaults, the files stated in the `extends`
+    /// field and the front matter of the document.
+    pub fn read(
+        file: &PathBuf,
+        config: &Config,
+        defaults: Option<&Value>,
+    ) -> Result<Self, SmoothError<'a>> {
+        let content = match fs::read_to_string(file) {
             Ok(x) => x,
-            Err(e) => {
-                Header::remove_template(json_tpl.clone())?;
-                return Err(SmoothError::MetadataParseFailure(e));
+            Err(e) => return Err(SmoothError::ReadSourceFailed(file.clone(), e)),
+        };
+        let document = match frontmatter::split(&content) {
+            (Some(x), _) => match frontmatter::parse(x) {
+                Ok(y) => y,
+                Err(e) => return Err(SmoothError::MetadataYamlParseFailure(file.clone(), e)),
+            },
+            (None, _) => Value::Object(Map::new()),
+        };
+        let parent = match file.parent() {
+            Some(x) => x.to_path_buf(),
+            None => return Err(SmoothError::NoParentFolder(file.clone())),
+        };
+
+        let mut layers = Layers::defaults(config, defaults);
+        // The `extends` field of the document replaces the one of the defaults.
+        let extends = layers
+            .iter()
+            .map(|x| &x.values)
+            .chain(std::iter::once(&document))
+            .filter_map(|x| x.get("extends"))
+            .next_back()
+            .cloned();
+        if let Some(x) = extends {
+            Layers::extend(&x, &parent, config, &mut vec![file.clone()], &mut layers)?;
+        }
+        layers.push(Layer {
+            source: Source::Document,
+            values: document,
+        });
+        for layer in layers.iter_mut() {
+            expand_template_folders(&mut layer.values, config)?;
+        }
+        Ok(Self {
+            layers,
+            bundle: None,
+        })
+    }
+
+    /// Returns the header values of the user configuration without any document.
+    pub fn read_config(config: &Config) -> Result<Self, SmoothError<'a>> {
+        let mut layers = Layers::defaults(config, None);
+        for layer in layers.iter_mut() {
+            expand_template_folders(&mut layer.values, config)?;
+        }
+        Ok(Self {
+            layers,
+            bundle: None,
+        })
+    }
+
+    /// Returns the layers of the user configuration and the optional defaults.
+    fn defaults(config: &Config, defaults: Option<&Value>) -> Vec<Layer> {
+        let mut rsl = Vec::new();
+        if let (Some(x), Some(path)) = (&config.defaults, &config.path) {
+            rsl.push(Layer {
+                source: Source::Config(path.clone()),
+                values: x.clone(),
+            });
+        }
+        if let Some(x) = defaults {
+            rsl.push(Layer {
+                source: Source::Defaults,
+                values: x.clone(),
+            });
+        }
+        rsl
+    }
+
+    /// Reads the files listed in an `extends` field relative to the given folder and appends
+    /// them to the layers. The files a file extends are added before the file itself, thus a
+    /// file always overrides the files it builds upon and later entries of the list override
+    /// earlier ones. The chain holds the files currently being extended to detect cycles.
+    fn extend(
+        extends: &Value,
+        wd: &PathBuf,
+        config: &Config,
+        chain: &mut Vec<PathBuf>,
+        layers: &mut Vec<Layer>,
+    ) -> Result<(), SmoothError<'a>> {
+        let entries: Vec<&Value> = match extends {
+            Value::Array(x) => x.iter().collect(),
+            x => vec![x],
+        };
+        for entry in entries {
+            let name = match entry.as_str() {
+                Some(x) if !x.trim().is_empty() => config.expand_path(x)?,
+                _ => continue,
+            };
+            let path = util::normalize_path(name, Some(wd))?;
+            if chain.contains(&path) {
+                let mut cycle = chain.clone();
+                cycle.push(path);
+                return Err(SmoothError::ExtendsCycle(cycle));
+            }
+            if !path.exists() {
+                return Err(SmoothError::ExtendsNotFound(path));
+            }
+            let content = match fs::read_to_string(&path) {
+                Ok(x) => x,
+                Err(e) => return Err(SmoothError::ReadSourceFailed(path, e)),
+            };
+            let mut values = match frontmatter::parse(&content) {
+                Ok(x) => x,
+                Err(e) => return Err(SmoothError::MetadataYamlParseFailure(path, e)),
+            };
+            let parent = match path.parent() {
+                Some(x) => x.to_path_buf(),
+                None => return Err(SmoothError::NoParentFolder(path)),
+            };
+            resolve_paths(&mut values, &parent);
+            if let Some(x) = values.get("extends") {
+                chain.push(path.clone());
+                Layers::extend(x, &parent, config, chain, layers)?;
+                chain.pop();
             }
+            layers.push(Layer {
+                source: Source::Extends(path),
+                values,
+            });
+        }
+        Ok(())
+    }
+
+    /// Applies the profile with the given name. The block of the profile in the merged
+    /// `profiles` field is added as the layer with the highest priority, thus it overrides the
+    /// values of all other layers.
+    pub fn select_profile(&mut self, name: &str) -> Result<(), SmoothError<'a>> {
+        let profiles = match Layers::merge(self.layers.iter()).get("profiles") {
+            Some(Value::Object(x)) => x.clone(),
+            _ => Map::new(),
         };
-        Header::remove_template(json_tpl.clone())?;
+        match profiles.get(name) {
+            Some(x) => {
+                self.layers.push(Layer {
+                    source: Source::Profile(String::from(name)),
+                    values: x.clone(),
+                });
+                Ok(())
+            }
+            None => Err(SmoothError::UnknownProfile(
+                String::from(name),
+                profiles.keys().cloned().collect(),
+            )),
+        }
+    }
 
-        debug!("parsed {:?}", data);
-        Ok(data)
+    /// Applies values given as `key=value` assignments (ex.: on the command line). They form the
+    /// layer with the highest priority. The key can be a dotted path to set a nested value (ex.:
+    /// `tera_context.customer=ACME`), the value is read as YAML. Relative paths are resolved
+    /// relative to the current working directory.
+    pub fn set_overrides(
+        &mut self,
+        assignments: &[String],
+        config: &Config,
+    ) -> Result<(), SmoothError<'a>> {
+        if assignments.is_empty() {
+            return Ok(());
+        }
+        let mut values = Value::Object(Map::new());
+        for assignment in assignments {
+            util::merge_values(&mut values, Layers::assignment(assignment)?);
+        }
+        expand_template_folders(&mut values, config)?;
+        match std::env::current_dir() {
+            Ok(x) => resolve_override_paths(&mut values, &x),
+            Err(_) => return Err(util::NormalizeError::WdNotFound.into()),
+        }
+        let mut effective = Vec::new();
+        Layers::flatten(String::new(), &values, &mut effective);
+        for (key, value) in effective {
+            debug!("{} set to {} on the command line", key, value);
+        }
+        self.layers.push(Layer {
+            source: Source::CommandLine,
+            values,
+        });
+        Ok(())
     }
 
-    /// Creates the temporary pandoc template for extracting the content of the header as JSON. The
-    /// template will be created in the systems temporary folder. If the file already exists the
-    /// execution will halt. Returns the path of the file.
-    fn create_template() -> Result<PathBuf, SmoothError<'a>> {
-        let path = env::temp_dir().join(PathBuf::from(JSON_TEMPLATE_PATH));
-        if path.exists() {
-            return Err(SmoothError::JsonTemplateExists(path));
+    /// Applies the template bundle stated in the `template_bundle` field. The values of the
+    /// bundle are inserted right below the layer stating the bundle, thus they behave like an
+    /// extended file of this layer. Bundles given by path are resolved relative to the given
+    /// folder of the document, bundles given by name are looked up in the template registry.
+    pub fn apply_bundle(
+        &mut self,
+        wd: &PathBuf,
+        registry: &TemplateRegistry,
+    ) -> Result<(), SmoothError<'a>> {
+        let name = match Layers::merge(self.layers.iter()).get("template_bundle") {
+            Some(Value::String(x)) if !x.trim().is_empty() => x.clone(),
+            _ => return Ok(()),
+        };
+        let position = self
+            .layers
+            .iter()
+            .rposition(|x| x.values.get("template_bundle").is_some())
+            .unwrap_or_default();
+        let bundle = Bundle::find(&name, wd, registry)?;
+        self.layers.insert(
+            position,
+            Layer {
+                source: Source::Bundle(bundle.path.clone()),
+                values: bundle.values.clone(),
+            },
+        );
+        self.bundle = Some(bundle);
+        Ok(())
+    }
+
+    /// Returns the applied template bundle.
+    pub fn bundle(&self) -> Option<&Bundle> {
+        self.bundle.as_ref()
+    }
+
+    /// Returns the layers ordered by priority.
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// Returns the merged values of all layers. The `profiles` field is removed as the selected
+    /// profile is already applied as a layer.
+    pub fn merged(&self) -> Value {
+        let mut rsl = Layers::merge(self.layers.iter());
+        if let Some(x) = rsl.as_object_mut() {
+            x.remove("profiles");
+        }
+        rsl
+    }
+
+    /// Returns the merged values of all layers for the conversion into the given output format.
+    /// The values of the format's block in the `formats` field are merged over the top-level
+    /// values. The odtpdf format uses the block of odt followed by it's own block. The `formats`
+    /// field itself is removed.
+    pub fn merged_for(&self, format: &OutputFormat) -> Value {
+        let mut rsl = self.merged();
+        let blocks = match rsl.as_object_mut().and_then(|x| x.remove("formats")) {
+            Some(Value::Object(x)) => x,
+            _ => return rsl,
+        };
+        let names: &[&str] = match format {
+            OutputFormat::OdtPdf => &["odt", "odtpdf"],
+            x => &[x.name()],
+        };
+        for name in names {
+            if let Some(x) = blocks.get(*name) {
+                util::merge_values(&mut rsl, x.clone());
+            }
+        }
+        rsl
+    }
+
+    /// Replaces the values of the document's front matter. Used to apply the front matter as
+    /// rendered by Tera.
+    pub fn set_document(
+        &mut self,
+        mut values: Value,
+        config: &Config,
+    ) -> Result<(), SmoothError<'a>> {
+        expand_template_folders(&mut values, config)?;
+        for layer in self.layers.iter_mut() {
+            if layer.source == Source::Document {
+                layer.values = values.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the paths of all files pulled in by the `extends` field and the manifest and the
+    /// filters of the template bundle.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut rsl = Vec::new();
+        for layer in self.layers.iter() {
+            if let Source::Extends(ref x) = layer.source {
+                if !rsl.contains(x) {
+                    rsl.push(x.clone());
+                }
+            }
+        }
+        if let Some(ref x) = self.bundle {
+            rsl.push(x.path.clone());
+            rsl.extend(x.filters.iter().cloned());
+        }
+        rsl
+    }
+
+    /// Returns every value of the merged header together with the source it originates from.
+    /// Nested values are stated by their dotted path (ex.: `tera_context.name`). Lists are
+    /// treated as a single value as they are replaced as a whole when merging.
+    pub fn sources(&self) -> Vec<(String, Value, Source)> {
+        let mut rsl: BTreeMap<String, (Value, Source)> = BTreeMap::new();
+        for layer in self.layers.iter() {
+            let mut values = Vec::new();
+            Layers::flatten(String::new(), &layer.values, &mut values);
+            for (key, value) in values {
+                // A value replaces everything nested below it and a nested value replaces the
+                // non-mapping values above it.
+                let prefix = format!("{}.", key);
+                rsl.retain(|x, _| {
+                    !x.starts_with(&prefix) && !prefix.starts_with(&format!("{}.", x))
+                });
+                rsl.insert(key, (value, layer.source.clone()));
+            }
+        }
+        rsl.into_iter().map(|(k, (v, s))| (k, v, s)).collect()
+    }
+
+    /// Collects all non-mapping values (and empty mappings) of a value with their dotted path.
+    fn flatten(prefix: String, value: &Value, rsl: &mut Vec<(String, Value)>) {
+        match value {
+            Value::Object(x) if !x.is_empty() => {
+                for (key, value) in x {
+                    let path = match prefix.is_empty() {
+                        true => key.clone(),
+                        false => format!("{}.{}", prefix, key),
+                    };
+                    Layers::flatten(path, value, rsl);
+                }
+            }
+            x if !prefix.is_empty() => rsl.push((prefix, x.clone())),
+            _ => {}
+        }
+    }
+
+    /// Parses a `key=value` assignment into a header value. The key is split at the dots into
+    /// nested mappings. The value is read the same way as a value in the front matter, values
+    /// which aren't valid YAML (ex.: `title=Offer: ACME`) or start with a `#` (which YAML treats as
+    /// a comment) are used as plain strings.
+    fn assignment(assignment: &str) -> Result<Value, SmoothError<'a>> {
+        let (key, value) = match assignment.find('=') {
+            Some(x) => (assignment[..x].trim(), &assignment[x + 1..]),
+            None => return Err(SmoothError::InvalidAssignment(String::from(assignment))),
+        };
+        if key.is_empty() || key.split('.').any(|x| x.is_empty()) {
+            return Err(SmoothError::InvalidAssignment(String::from(assignment)));
+        }
+        let mut rsl = match frontmatter::parse(&format!("value: {}", value)) {
+            Ok(Value::Object(mut x)) if x.len() == 1 && !value.trim_start().starts_with('#') => {
+                x.remove("value").unwrap_or_default()
+            }
+            _ => Value::from(value),
+        };
+        for part in key.rsplit('.') {
+            let mut map = Map::new();
+            map.insert(String::from(part), rsl);
+            rsl = Value::Object(map);
         }
-        let mut file = match fs::File::create(&path) {
+        Ok(rsl)
+    }
+
+    /// Merges the given layers in order.
+    fn merge<'b, I: Iterator<Item = &'b Layer>>(layers: I) -> Value {
+        let mut rsl = Value::Object(Map::new());
+        for layer in layers {
+            util::merge_values(&mut rsl, layer.values.clone());
+        }
+        rsl
+    }
+}
+
+impl<'a> Header {
+    /// Returns the header for the given (merged) header values. The values are interpreted the
+    /// same way pandoc does (see the frontmatter module).
+    pub fn from(values: Value) -> Result<Self, SmoothError<'a>> {
+        let data: Self = match serde_json::from_value(values) {
             Ok(x) => x,
-            Err(e) => return Err(SmoothError::CreateJsonTemplateFailed(path, e)),
+            Err(e) => return Err(SmoothError::MetadataParseFailure(e)),
         };
-        match file.write_all(JSON_TEMPLATE.as_bytes()) {
-            Ok(_) => Ok(path),
-            Err(e) => Err(SmoothError::CreateJsonTemplateFailed(path, e)),
+
+        debug!("parsed {:?}", data);
+        Ok(data)
+    }
+
+    /// Returns the absolute paths of the Tera context files stated in the header. Relative paths
+    /// are resolved relative to the given folder of the document.
+    pub fn tera_context_files(&self, parent: &PathBuf) -> Result<Vec<PathBuf>, SmoothError<'a>> {
+        let mut rsl = Vec::new();
+        if let Some(ref x) = self.tera_context_files {
+            for file in x.clone().into_vec() {
+                if let Some(y) = non_empty(Some(file)) {
+                    rsl.push(util::normalize_path(y, Some(parent))?);
+                }
+            }
         }
+        Ok(rsl)
     }
 
-    /// Removes the temporary metadata as JSON template with the given path.
-    fn remove_template(path: PathBuf) -> Result<(), SmoothError<'a>> {
-        match fs::remove_file(&path) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(SmoothError::RemoveJsonTemplateFailed(path, e)),
+    /// Returns the output formats stated in the header. Returns an empty list if no format is
+    /// set.
+    pub fn formats(&self) -> Result<Vec<OutputFormat>, SmoothError<'a>> {
+        match self.format {
+            Some(ref x) => util::parse_formats(x.clone().into_vec()),
+            None => Ok(vec![]),
         }
     }
 }
 
 /// States the file type a path points to. This is used to normalize paths and returning the
 /// appropriate error message.
+#[derive(Clone, Copy)]
 enum PathType {
-    /// Path to a template file.
-    Template,
     /// Path to a reference file.
     Reference,
     /// Path to bibliography file.
     Bibliography,
-    /// Path to citation style file.
-    CitationStyle,
+    /// Path to a file included into the output.
+    Include,
+    /// Path to a YAML metadata file.
+    MetadataFile,
 }
 
 #[derive(Debug, Clone)]
@@ -145,39 +724,61 @@ pub struct Metadata {
     pub engine: String,
     /// Set additional parameters to pandoc.
     pub pandoc_options: Option<Vec<String>>,
-    /// Whether the content of the input file should be feed into the Terra templating engine.
-    pub do_tera: bool,
-    /// Optional template context aka. variables etc.
-    pub tera_context: Option<HashMap<String, Value>>,
+    /// Whether a table of contents should be included.
+    pub toc: bool,
+    /// Number of section levels included in the table of contents.
+    pub toc_depth: Option<u8>,
+    /// Whether the sections should be numbered.
+    pub number_sections: bool,
+    /// Style used for the syntax highlighting of code.
+    pub highlight_style: Option<String>,
+    /// Language of the document.
+    pub lang: Option<String>,
+    /// Files included at the end of the header.
+    pub include_in_header: Vec<PathBuf>,
+    /// Files included at the beginning of the document body.
+    pub include_before_body: Vec<PathBuf>,
+    /// Template variables as name and value. A variable can occur multiple times (lists).
+    pub variables: Vec<(String, Option<String>)>,
+    /// Additional YAML files pandoc reads metadata from. Values set in the document itself take
+    /// priority over the values of these files.
+    pub metadata_files: Vec<PathBuf>,
     /// Whether newline should break text in description texts. This is especially useful when
     /// using description lists for screen- and stageplays.
     pub break_description: bool,
-    /// Path to bibliography file (JSON CTL).
-    pub bibliography: Option<PathBuf>,
+    /// The filter pipeline stated in the header, applied after the Lua filters.
+    pub filters: Vec<PandocFilter>,
+    /// Paths to the Lua filters applied by pandoc.
+    pub lua_filters: Vec<PathBuf>,
+    /// Additional folders pandoc searches for images and other resources (ex.: the asset folders
+    /// of a template bundle).
+    pub resource_paths: Vec<PathBuf>,
+    /// Paths to the bibliography files.
+    pub bibliography: Vec<PathBuf>,
     /// Optional path to the Citation Style Language file, altering the citation style.
     pub csl: Option<PathBuf>,
+    /// Maximal time the external tools may run, they are stopped afterwards.
+    pub timeout: Option<Duration>,
 }
 
 impl<'a> Metadata {
-    /// Tries to read the YAML header of a given input file to a Metadata struct using pandoc. The
-    /// function will test the paths.
+    /// Returns the metadata for the conversion into the given output format based on the header
+    /// of the document. The function will resolve and test the paths relative to the given
+    /// parent folder. Templates given by name are looked up in the template registry, citation
+    /// styles given by name in the style folders of the configuration.
     pub fn from(
-        path: &PathBuf,
+        header: Header,
         parent: &PathBuf,
         output_format: &OutputFormat,
+        config: &Config,
+        templates: &TemplateRegistry,
     ) -> Result<Self, SmoothError<'a>> {
-        let header = Header::from(path)?;
         Ok(Self {
-            template: match header.template {
-                Some(x) => Some(Metadata::normalize_path(
-                    x,
-                    parent,
-                    PathType::Template,
-                    output_format,
-                )?),
+            template: match non_empty(header.template) {
+                Some(x) => Some(Metadata::template(x, parent, output_format, templates)?),
                 None => None,
             },
-            reference: match header.reference {
+            reference: match non_empty(header.reference) {
                 Some(x) => Some(Metadata::normalize_path(
                     x,
                     parent,
@@ -187,34 +788,303 @@ impl<'a> Metadata {
                 None => None,
             },
             engine: header.engine,
-            pandoc_options: match header.pandoc_optons {
-                Some(x) => Some(x.split_whitespace().map(|y| String::from(y)).collect()),
+            pandoc_options: match header.pandoc_options {
+                Some(OneOrMany::One(x)) => Some(util::split_args(&x)),
+                Some(OneOrMany::Many(x)) => Some(x),
+                None => None,
+            },
+            toc: header.toc,
+            toc_depth: match header.toc_depth {
+                Some(x) => Metadata::toc_depth(x)?,
+                None => None,
+            },
+            number_sections: header.number_sections,
+            highlight_style: match non_empty(header.highlight_style) {
+                Some(x) => Some(Metadata::highlight_style(x, parent)?),
                 None => None,
             },
-            do_tera: header.do_tera,
-            tera_context: header.tera_context,
+            lang: non_empty(header.lang),
+            include_in_header: Metadata::normalize_paths(
+                header.include_in_header,
+                parent,
+                PathType::Include,
+                output_format,
+            )?,
+            include_before_body: Metadata::normalize_paths(
+                header.include_before_body,
+                parent,
+                PathType::Include,
+                output_format,
+            )?,
+            variables: match header.variables {
+                Some(x) => Metadata::variables(x)?,
+                None => vec![],
+            },
+            metadata_files: Metadata::normalize_paths(
+                header.metadata_files,
+                parent,
+                PathType::MetadataFile,
+                output_format,
+            )?,
             break_description: header.break_description,
-            bibliography: match header.bibliography {
-                Some(x) => Some(Metadata::normalize_path(
-                    x,
-                    parent,
-                    PathType::Bibliography,
-                    output_format,
-                )?),
+            filters: Metadata::filters(header.filters, parent)?,
+            lua_filters: vec![],
+            resource_paths: vec![],
+            bibliography: Metadata::normalize_paths(
+                header.bibliography,
+                parent,
+                PathType::Bibliography,
+                output_format,
+            )?,
+            csl: match non_empty(header.csl) {
+                Some(x) => Some(Metadata::citation_style(x, parent, config)?),
                 None => None,
             },
-            csl: match header.csl {
-                Some(x) => Some(Metadata::normalize_path(
-                    x,
-                    parent,
-                    PathType::CitationStyle,
-                    output_format,
-                )?),
+            timeout: match header.timeout {
+                Some(x) => Metadata::timeout(x)?,
                 None => None,
             },
         })
     }
 
+    /// Returns the paths of all files referenced by the header (template, reference, bibliography,
+    /// citation style, includes and metadata files). Used to determine which files a conversion
+    /// depends upon.
+    pub fn files(&self) -> Vec<PathBuf> {
+        vec![&self.template, &self.reference, &self.csl]
+            .into_iter()
+            .flatten()
+            .chain(self.bibliography.iter())
+            .chain(self.filters.iter().filter_map(|x| match x {
+                PandocFilter::Lua(y) | PandocFilter::Json(y) => Some(y),
+                _ => None,
+            }))
+            .chain(self.include_in_header.iter())
+            .chain(self.include_before_body.iter())
+            .chain(self.metadata_files.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Parses the depth of the table of contents. Front matter numbers are read as strings while
+    /// the defaults of configuration files may contain real numbers, both are accepted.
+    fn toc_depth(value: Value) -> Result<Option<u8>, SmoothError<'a>> {
+        let depth = match value {
+            Value::String(ref x) if x.trim().is_empty() => return Ok(None),
+            Value::String(ref x) => x.trim().parse::<u8>().ok(),
+            Value::Number(ref x) => x.as_u64().filter(|y| *y <= 255).map(|y| y as u8),
+            _ => None,
+        };
+        match depth {
+            Some(x) => Ok(Some(x)),
+            None => Err(SmoothError::InvalidFieldValue(
+                String::from("toc_depth"),
+                value.to_string(),
+                "a positive number",
+            )),
+        }
+    }
+
+    /// Parses the timeout given in seconds. Fractions of a second are allowed, strings and
+    /// numbers are accepted the same way as for the depth of the table of contents.
+    fn timeout(value: Value) -> Result<Option<Duration>, SmoothError<'a>> {
+        let seconds = match value {
+            Value::String(ref x) if x.trim().is_empty() => return Ok(None),
+            Value::String(ref x) => x.trim().parse::<f64>().ok(),
+            Value::Number(ref x) => x.as_f64(),
+            _ => None,
+        };
+        match seconds {
+            Some(x) if x > 0.0 && x.is_finite() => Ok(Some(Duration::from_secs_f64(x))),
+            _ => Err(SmoothError::InvalidFieldValue(
+                String::from("timeout"),
+                value.to_string(),
+                "a positive number of seconds",
+            )),
+        }
+    }
+
+    /// Converts the variables of the header into pandoc variables. Lists result in multiple
+    /// variables with the same name, `true` in a variable without a value and `false` in no
+    /// variable at all. Nested maps are not supported.
+    fn variables(
+        variables: Map<String, Value>,
+    ) -> Result<Vec<(String, Option<String>)>, SmoothError<'a>> {
+        let mut rsl = Vec::new();
+        for (name, value) in variables {
+            let values = match value {
+                Value::Array(x) => x,
+                x => vec![x],
+            };
+            for value in values {
+                match value {
+                    Value::Bool(true) => rsl.push((name.clone(), None)),
+                    Value::Bool(false) | Value::Null => {}
+                    Value::String(x) => rsl.push((name.clone(), Some(x))),
+                    Value::Number(x) => rsl.push((name.clone(), Some(x.to_string()))),
+                    x => {
+                        return Err(SmoothError::InvalidFieldValue(
+                            format!("variables.{}", name),
+                            x.to_string(),
+                            "a string, number, boolean or a list of these",
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(rsl)
+    }
+
+    /// Normalizes a path field which can contain one or multiple paths. Empty values are
+    /// ignored.
+    fn normalize_paths(
+        paths: Option<OneOrMany<String>>,
+        parent: &PathBuf,
+        typ: PathType,
+        output_format: &OutputFormat,
+    ) -> Result<Vec<PathBuf>, SmoothError<'a>> {
+        let mut rsl = Vec::new();
+        for path in paths.map(|x| x.into_vec()).unwrap_or_default() {
+            if let Some(x) = non_empty(Some(path)) {
+                rsl.push(Metadata::normalize_path(x, parent, typ, output_format)?);
+            }
+        }
+        Ok(rsl)
+    }
+
+    /// Resolves the template. The value is either a path (relative to the given folder of the
+    /// document) or the name of a template (ex.: `letter`) which is looked up in the template
+    /// registry.
+    fn template(
+        template: String,
+        parent: &PathBuf,
+        output_format: &OutputFormat,
+        templates: &TemplateRegistry,
+    ) -> Result<PathBuf, SmoothError<'a>> {
+        let path = util::normalize_path(&template, Some(parent))?;
+        if path.exists() {
+            return Ok(path);
+        }
+        if template.contains(&['/', '\\'][..]) {
+            return Err(SmoothError::TemplateNotFound(path));
+        }
+        match templates.find(&template, output_format) {
+            Some(x) => Ok(x),
+            None => {
+                debug!(
+                    "template {} not found in {}",
+                    template,
+                    templates
+                        .folders()
+                        .iter()
+                        .map(|(x, _)| x.display().to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                );
+                Err(SmoothError::TemplateNotFound(PathBuf::from(template)))
+            }
+        }
+    }
+
+    /// Resolves the style of the syntax highlighting. A theme file (ending in `.theme` or an
+    /// existing file) is resolved relative to the given folder of the document like a template,
+    /// all other values name a style of pandoc and are taken as they are.
+    fn highlight_style(style: String, parent: &PathBuf) -> Result<String, SmoothError<'a>> {
+        let path = util::normalize_path(&style, Some(parent))?;
+        let theme = Path::new(&style).extension().map(|x| x == "theme").unwrap_or(false);
+        match (path.is_file(), theme) {
+            (true, _) => Ok(path.to_string_lossy().to_string()),
+            (false, true) => Err(SmoothError::HighlightThemeNotFound(path)),
+            (false, false) => Ok(style),
+        }
+    }
+
+    /// Resolves the entries of the `filters` field. Filters of the library given by name are
+    /// saved into temporary files, `citeproc` is taken as it is, other entries are paths relative
+    /// to the given folder of the document. Files ending in `.lua` are Lua filters, all others
+    /// JSON filters. A JSON filter given by a plain name which isn't a file in the folder is
+    /// looked up in the `PATH` (ex.: `pandoc-crossref`).
+    fn filters(
+        filters: Option<OneOrMany<String>>,
+        parent: &PathBuf,
+    ) -> Result<Vec<PandocFilter>, SmoothError<'a>> {
+        let mut rsl = Vec::new();
+        for filter in filters.map(|x| x.into_vec()).unwrap_or_default() {
+            let filter = filter.trim();
+            if filter.is_empty() {
+                continue;
+            }
+            if filter == "citeproc" {
+                rsl.push(PandocFilter::Citeproc);
+                continue;
+            }
+            if LuaFilter::is_library(filter) {
+                rsl.push(PandocFilter::Library(Arc::new(LuaFilter::named(filter)?)));
+                continue;
+            }
+            let mut path = util::normalize_path(filter, Some(parent))?;
+            if !path.exists() && !filter.contains(&['/', '\\'][..]) {
+                let found = env::var_os("PATH").and_then(|x| {
+                    env::split_paths(&x)
+                        .map(|y| y.join(filter))
+                        .find(|y| y.is_file())
+                });
+                if let Some(x) = found {
+                    path = x;
+                }
+            }
+            if !path.exists() {
+                return Err(SmoothError::FilterNotFound(path));
+            }
+            rsl.push(match path.extension().and_then(|x| x.to_str()) {
+                Some("lua") => PandocFilter::Lua(path),
+                _ => PandocFilter::Json(path),
+            });
+        }
+        Ok(rsl)
+    }
+
+    /// Resolves the citation style. The value is either a path (relative to the given folder of
+    /// the document) or the name of a style (ex.: `apa` or `chicago-note.csl`). Names are looked up
+    /// in the folder of the document followed by the style folders of the configuration, the
+    /// `.csl` extension is added if missing.
+    fn citation_style(
+        style: String,
+        parent: &PathBuf,
+        config: &Config,
+    ) -> Result<PathBuf, SmoothError<'a>> {
+        let path = util::normalize_path(&style, Some(parent))?;
+        if path.exists() {
+            return Ok(path);
+        }
+        if style.contains(&['/', '\\'][..]) {
+            return Err(SmoothError::CitationStyleNotFound(path));
+        }
+        let file = match style.ends_with(".csl") {
+            true => style,
+            false => format!("{}.csl", style),
+        };
+        let folders: Vec<PathBuf> = std::iter::once(parent.clone())
+            .chain(config.style_folders())
+            .collect();
+        for folder in folders.iter() {
+            let candidate = folder.join(&file);
+            if candidate.exists() {
+                return Ok(candidate);
+            }
+        }
+        debug!(
+            "citation style {} not found in {}",
+            file,
+            folders
+                .iter()
+                .map(|x| x.display().to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        Err(SmoothError::CitationStyleNotFound(PathBuf::from(file)))
+    }
+
     /// Takes the path to a file and returns a normalized absolute PathBuf. Also tests if
     /// the file exists. If the path points to a reference the correct file type for the given
     /// output format is also checked.
@@ -226,7 +1096,7 @@ impl<'a> Metadata {
     ) -> Result<PathBuf, SmoothError<'a>> {
         let rsl = util::normalize_path(&path, Some(parent))?;
         if let PathType::Reference = typ {
-            let extension = rsl.extension().unwrap().to_str().unwrap();
+            let extension = rsl.extension().and_then(|x| x.to_str()).unwrap_or("");
             match output_format {
                 OutputFormat::Odt => match extension {
                     "odt" | "fodt" => {}
@@ -242,11 +1112,155 @@ impl<'a> Metadata {
         match rsl.exists() {
             true => Ok(rsl),
             false => match typ {
-                PathType::Template => Err(SmoothError::TemplateNotFound(rsl)),
                 PathType::Reference => Err(SmoothError::ReferenceNotFound(rsl)),
                 PathType::Bibliography => Err(SmoothError::BibliographyNotFound(rsl)),
-                PathType::CitationStyle => Err(SmoothError::CitationStyleNotFound(rsl)),
+                PathType::Include => Err(SmoothError::IncludeNotFound(rsl)),
+                PathType::MetadataFile => Err(SmoothError::MetadataFileNotFound(rsl)),
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Layers, Source};
+    use crate::config::Config;
+    use crate::error::SmoothError;
+    use crate::registry::TemplateRegistry;
+    use crate::OutputFormat;
+
+    use std::fs;
+
+    use serde_json::json;
+
+    #[test]
+    fn extends() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |x: &str| dir.path().join(x);
+        fs::write(path("base.yaml"), "engine: pdflatex\ntera_context:\n  a: 1\n  b: 2\n").unwrap();
+        fs::write(path("letter.yaml"), "extends: base.yaml\ntera_context:\n  b: 3\n").unwrap();
+        fs::write(path("doc.md"), "---\nextends: letter.yaml\ntera_context:\n  c: 4\n---\n")
+            .unwrap();
+
+        let layers = Layers::read(&path("doc.md"), &Config::default(), None).unwrap();
+        assert_eq!(layers.files(), vec![path("base.yaml"), path("letter.yaml")]);
+        let merged = layers.merged();
+        assert_eq!(merged["engine"], json!("pdflatex"));
+        assert_eq!(merged["tera_context"], json!({"a": "1", "b": "3", "c": "4"}));
+
+        fs::write(path("base.yaml"), "extends: letter.yaml\n").unwrap();
+        match Layers::read(&path("doc.md"), &Config::default(), None) {
+            Err(SmoothError::ExtendsCycle(x)) => assert_eq!(x.len(), 4),
+            _ => panic!("cycle not detected"),
+        }
+    }
+
+    #[test]
+    fn bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |x: &str| dir.path().join(x);
+        fs::create_dir_all(path("corp")).unwrap();
+        fs::write(
+            path("corp/bundle.yaml"),
+            "engine: xelatex\ntoc: true\nlang: de\nfilters: [quotes.lua]\n",
+        )
+        .unwrap();
+        fs::write(path("corp/quotes.lua"), "return {}\n").unwrap();
+        fs::write(path("base.yaml"), "template_bundle: corp\nlang: en\n").unwrap();
+        fs::write(path("doc.md"), "---\nextends: base.yaml\ntoc: false\n---\n").unwrap();
+
+        let mut layers = Layers::read(&path("doc.md"), &Config::default(), None).unwrap();
+        let registry = TemplateRegistry::new(dir.path(), &Config::default());
+        layers.apply_bundle(&dir.path().to_path_buf(), &registry).unwrap();
+        // The bundle is placed right below the layer stating it, like an extended file.
+        let sources: Vec<&Source> = layers.layers().iter().map(|x| &x.source).collect();
+        assert_eq!(
+            sources,
+            vec![
+                &Source::Bundle(path("corp/bundle.yaml")),
+                &Source::Extends(path("base.yaml")),
+                &Source::Document,
+            ]
+        );
+        let merged = layers.merged();
+        assert_eq!(merged["engine"], json!("xelatex"));
+        assert_eq!(merged["lang"], json!("en"));
+        assert_eq!(merged["toc"], json!(false));
+        assert_eq!(layers.bundle().unwrap().filters, vec![path("corp/quotes.lua")]);
+    }
+
+    #[test]
+    fn profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let doc = dir.path().join("doc.md");
+        fs::write(
+            &doc,
+            "---\ntoc: false\nwatermark: none\nprofiles:\n  draft:\n    watermark: INTERNAL\n    \
+             formats:\n      pdf:\n        engine: xelatex\nformats:\n  pdf:\n    engine: \
+             pdflatex\n---\n",
+        )
+        .unwrap();
+        let defaults = json!({"profiles": {"draft": {"toc": true, "watermark": "DRAFT"}}});
+
+        let mut layers = Layers::read(&doc, &Config::default(), Some(&defaults)).unwrap();
+        layers.select_profile("draft").unwrap();
+        let merged = layers.merged();
+        assert_eq!(merged["toc"], json!(true));
+        assert_eq!(merged["watermark"], json!("INTERNAL"));
+        assert_eq!(layers.merged_for(&OutputFormat::Pdf)["engine"], json!("xelatex"));
+        assert_eq!(layers.merged_for(&OutputFormat::Docx).get("engine"), None);
+
+        layers
+            .set_overrides(&[String::from("watermark=FINAL")], &Config::default())
+            .unwrap();
+        assert_eq!(layers.merged()["watermark"], json!("FINAL"));
+
+        match layers.select_profile("final") {
+            Err(SmoothError::UnknownProfile(_, x)) => assert_eq!(x, vec!["draft"]),
+            _ => panic!("unknown profile accepted"),
+        }
+    }
+
+    #[test]
+    fn assignment() {
+        assert_eq!(
+            Layers::assignment("tera_context.customer.name=ACME").unwrap(),
+            json!({"tera_context": {"customer": {"name": "ACME"}}})
+        );
+        assert_eq!(Layers::assignment("toc=true").unwrap(), json!({"toc": true}));
+        assert_eq!(
+            Layers::assignment("format=[pdf, docx]").unwrap(),
+            json!({"format": ["pdf", "docx"]})
+        );
+        assert_eq!(
+            Layers::assignment("title=Offer: ACME").unwrap(),
+            json!({"title": "Offer: ACME"})
+        );
+        assert!(Layers::assignment("toc").is_err());
+        assert!(Layers::assignment("a..b=1").is_err());
+    }
+
+    #[test]
+    fn override_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let wd = dir.path().to_path_buf();
+        let mut values = json!({
+            "template": "acme.tex",
+            "csl": "apa",
+            "filters": ["pagebreak", "citeproc", "filters/a.lua", "@shared/b.lua"],
+            "formats": {"docx": {"reference": "https://example.com/ref.docx"}},
+        });
+        super::resolve_override_paths(&mut values, &wd);
+        let path = |x: &str| json!(wd.join(x).to_str().unwrap());
+        assert_eq!(values["template"], path("acme.tex"));
+        assert_eq!(values["csl"], json!("apa"));
+        assert_eq!(
+            values["filters"],
+            json!(["pagebreak", "citeproc", path("filters/a.lua"), "@shared/b.lua"])
+        );
+        assert_eq!(
+            values["formats"]["docx"]["reference"],
+            json!("https://example.com/ref.docx")
+        );
+    }
+}
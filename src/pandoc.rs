@@ -1,19 +1,25 @@
 /// This module contains all functions to call pandoc and handle any errors occurring mine while.
+use crate::filter::PandocFilter;
+use crate::latex::Report;
 use crate::metadata::Metadata;
+use crate::process::{self, CancelHandle, ProcessError};
+use crate::version::Version;
+use crate::warning::{self, Warning};
 
 use std::env;
 use std::fmt;
-use std::io::{self, Error as IOError, ErrorKind};
+use std::io::{Error as IOError, ErrorKind};
 use std::path::PathBuf;
 use std::process::{Command, Output};
+use std::time::Duration;
 
 /// Default name of the pandoc executable. Will be used when no other name is defined via the
 /// `PANDOC_ENV` constant of this module.
-const PANDOC_CMD: &str = "pandoc";
+pub const PANDOC_CMD: &str = "pandoc";
 
 /// Name of the environment variable which will be used to determine the name of the pandoc
 /// executable.
-const PANDOC_ENV: &str = "PANDOC_CMD";
+pub const PANDOC_ENV: &str = "PANDOC_CMD";
 
 /// Contains information about the calling of the pandoc command. Used to accompany error messages
 /// when the pandoc execution fails.
@@ -26,32 +32,41 @@ pub struct DebugInfo {
     output: String,
     /// Pandoc stderr.
     err: String,
-    /// States whether pandoc was called for extracting the metadata in the header or not. This
-    /// alters the error message.
-    tried_extracting_header: bool,
+    /// Version of pandoc if known.
+    version: Option<String>,
+}
+
+impl DebugInfo {
+    /// Returns the standard error output of pandoc.
+    pub fn stderr(&self) -> &str {
+        &self.err
+    }
+
+    /// Sets the explanation of a failed PDF conversion. The explanation replaces the output of
+    /// pandoc (and thus the log of the LaTeX engine) in the error message, the complete log is
+    /// kept in the folder stated by the report.
+    pub fn explain(&mut self, report: &Report) {
+        self.err = report.to_string();
+    }
 }
 
 impl fmt::Display for DebugInfo {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.tried_extracting_header {
-            true => write!(
-                f,
-                "pandoc failed to extract header metadata from \"{}\" {}",
-                self.input, self.err,
-            ),
-
-            false => write!(
-                f,
-                "pandoc failed to convert \"{}\" to \"{}\" with template \"{}\" {}",
-                self.input,
-                self.output,
-                match self.template {
-                    Some(ref x) => String::from(x),
-                    None => String::from("<undefined>"),
-                },
-                self.err,
-            ),
-        }
+        write!(
+            f,
+            "pandoc {}failed to convert \"{}\" to \"{}\" with template \"{}\" {}",
+            match self.version {
+                Some(ref x) => format!("{} ", x),
+                None => String::new(),
+            },
+            self.input,
+            self.output,
+            match self.template {
+                Some(ref x) => String::from(x),
+                None => String::from("<undefined>"),
+            },
+            self.err,
+        )
     }
 }
 
@@ -72,6 +87,13 @@ pub enum PandocError<'a> {
     /// Couldn't call the pandoc command but the executable is on the system with the used name,
     /// thus not a NotFound error.
     CallFailed(IOError),
+    /// The resource folders couldn't be joined into the value of `--resource-path` as a folder
+    /// contains the path separator of the system (`:` or `;` on Windows).
+    InvalidResourcePath,
+    /// Pandoc didn't finish within the timeout and was killed. Contains the timeout.
+    ExecutionTimeout(Duration),
+    /// Pandoc was killed as the conversion was cancelled.
+    Cancelled,
 }
 
 impl fmt::Display for PandocError<'_> {
@@ -80,12 +102,12 @@ impl fmt::Display for PandocError<'_> {
             PandocError::NotFound(executable) => match executable == &PANDOC_CMD {
                 true => write!(
                     f,
-                    "couldn't find \"pandoc\" on your system, use the env \"{}\" to use an non default executable name",
+                    "couldn't find \"pandoc\" on your system, use the env \"{}\" or the pandoc field of the configuration file to use an non default executable name",
                     PANDOC_ENV
                 ),
                 false => write!(
                     f,
-                    "couldn't find pandoc with the executable name \"{}\" use env \"{}\" to specify otherwise",
+                    "couldn't find pandoc with the executable name \"{}\" use env \"{}\" or the configuration file to specify otherwise",
                     executable,
                     PANDOC_ENV
                 ),
@@ -96,6 +118,10 @@ impl fmt::Display for PandocError<'_> {
                 purpose,
                 path.display()
             ),
+            PandocError::InvalidResourcePath => write!(
+                f,
+                "couldn't pass the resource folders to pandoc, a folder contains the path separator"
+            ),
             PandocError::StringFromUtf8 => write!(
                 f,
                 "couldn't convert standard output (stdout) from pandoc"
@@ -110,51 +136,71 @@ impl fmt::Display for PandocError<'_> {
                 "couldn't call pandoc {}",
                 err,
             ),
+            PandocError::ExecutionTimeout(timeout) => write!(
+                f,
+                "pandoc didn't finish within {} seconds and was stopped",
+                timeout.as_secs_f64()
+            ),
+            PandocError::Cancelled => write!(f, "pandoc was stopped as the conversion was cancelled"),
         }
     }
 }
 
 /// Wrapper for calling pandoc. Exposes all needed functionality via it's method. Contains the
-/// executable name for pandoc.
-pub struct Pandoc(String);
+/// executable name for pandoc and it's version if known.
+pub struct Pandoc {
+    /// Name or path of the pandoc executable.
+    executable: String,
+    /// Version of pandoc, used to choose the command line options. `None` if unknown, in this
+    /// case the options of the current pandoc version are used.
+    version: Option<Version>,
+    /// Handle to cancel the running pandoc calls.
+    cancel: CancelHandle,
+}
 
 impl<'a> Pandoc {
-    /// Returns a new instance of the Pandoc struct. Will use the `PANDOC_CMD` environment variable
-    /// to determine the name of the pandoc executable. If the variable isn't set the constant
-    /// PANDOC_CMD will be used.
-    pub fn new() -> Self {
-        Self(match env::var(PANDOC_ENV) {
-            Ok(x) => x,
-            Err(_) => String::from(PANDOC_CMD),
+    /// Returns a new instance of the Pandoc struct using the given executable and determines the
+    /// version of pandoc by calling `pandoc --version`. The executable is determined by the user
+    /// configuration (see `Config::pandoc`). The command line options passed to pandoc are chosen
+    /// according to this version. All calls of the instance are stopped when the given handle
+    /// gets cancelled.
+    pub fn detect(executable: String, cancel: CancelHandle) -> Result<Self, PandocError<'a>> {
+        let mut cmd = Command::new(&executable);
+        cmd.arg("--version");
+        let stdout = match process::output(&mut cmd, None, &cancel) {
+            Ok(x) if x.status.success() => String::from_utf8_lossy(&x.stdout).into_owned(),
+            Ok(_) => String::new(),
+            Err(e) => return Err(Pandoc::process_error(e, executable)),
+        };
+        // The first line reads like `pandoc 3.1.2` (or `pandoc.exe 3.1.2` on Windows).
+        let version = stdout
+            .lines()
+            .next()
+            .and_then(|x| x.split_whitespace().nth(1))
+            .and_then(|x| x.parse::<Version>().ok());
+        match version {
+            Some(ref x) => debug!("detected pandoc {} ({})", x, executable),
+            None => warn!("couldn't determine the version of pandoc ({})", executable),
+        }
+        Ok(Self {
+            executable,
+            version,
+            cancel,
         })
     }
 
-    /// Converts a given file with a template and returns the result as a string. This function is
-    /// mainly used for the extraction of the frontmatter header as a JSON file.
-    pub fn convert_with_template_to_str(
-        &self,
-        input: &PathBuf,
-        template: PathBuf,
-    ) -> Result<String, PandocError<'a>> {
-        check_path(input.clone(), "input")?;
-        check_path(template.clone(), "template")?;
-        debug!(
-            "input: {}, template: {}",
-            input.display(),
-            template.display()
-        );
-
-        let mut cmd = Command::new(self.0.clone());
-        cmd.arg("--template").arg(template).arg(&input);
-
-        Pandoc::output_to_result(
-            cmd.output(),
-            self.0.clone(),
-            true,
-            String::from(input.to_str().unwrap()),
-            String::new(),
-            None,
-        )
+    /// Returns the version of pandoc if known.
+    pub fn version(&self) -> Option<&Version> {
+        self.version.as_ref()
+    }
+
+    /// Whether the pandoc version is at least the given version. Returns true if the version is
+    /// unknown.
+    fn at_least(&self, version: &[u32]) -> bool {
+        match self.version {
+            Some(ref x) => x >= &Version::new(version),
+            None => true,
+        }
     }
 
     /// Converts a given file with a template to a PDF. Optionally it's possible to add
@@ -168,39 +214,38 @@ impl<'a> Pandoc {
         metadata: Metadata,
         output: &PathBuf,
         resource_path: Option<&PathBuf>,
-    ) -> Result<(), PandocError<'a>> {
-        let mut cmd = Command::new(self.0.clone());
-        cmd.arg(&input)
-            .arg("--pdf-engine")
-            .arg(metadata.engine)
-            .arg("--wrap=preserve");
-        if let Some(ref template) = metadata.template {
-            cmd.arg("--template").arg(template);
-        }
-        if let Some(options) = metadata.pandoc_options {
-            cmd.args(options);
-        }
-        if let Some(_bibliography) = metadata.bibliography {
-            cmd.arg("--citeproc");
-        }
-        if let Some(path) = resource_path {
-            cmd.arg("--resource-path").arg(path);
-        }
-        cmd.arg("-o").arg(&output);
-        match Pandoc::output_to_result(
-            cmd.output(),
-            self.0.clone(),
-            false,
-            String::from(input.to_str().unwrap()),
-            String::from(output.to_str().unwrap()),
-            match metadata.template {
-                Some(x) => Some(String::from(x.to_str().unwrap())),
-                None => None,
-            },
-        ) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
-        }
+    ) -> Result<Vec<Warning>, PandocError<'a>> {
+        check_path(input.clone(), "input")?;
+        check_path(output.clone(), "output")?;
+        let mut cmd = Command::new(self.executable.clone());
+        // The option was called --latex-engine before pandoc 2.0.
+        cmd.arg(match self.at_least(&[2, 0]) {
+            true => "--pdf-engine",
+            false => "--latex-engine",
+        })
+            .arg(&metadata.engine);
+        Pandoc::add_latex_options(&mut cmd, &metadata);
+        let template = metadata.template.clone();
+        self.run(cmd, metadata, input, output, resource_path, template)
+    }
+
+    /// Converts a given file into the standalone LaTeX document pandoc generates while the
+    /// conversion into a PDF. Takes the same arguments as `convert_with_metadata_to_pdf`, used to
+    /// analyze a failed PDF conversion.
+    pub fn convert_with_metadata_to_latex(
+        &self,
+        input: &PathBuf,
+        metadata: Metadata,
+        output: &PathBuf,
+        resource_path: Option<&PathBuf>,
+    ) -> Result<Vec<Warning>, PandocError<'a>> {
+        check_path(input.clone(), "input")?;
+        check_path(output.clone(), "output")?;
+        let mut cmd = Command::new(self.executable.clone());
+        cmd.arg("-t").arg("latex").arg("--standalone");
+        Pandoc::add_latex_options(&mut cmd, &metadata);
+        let template = metadata.template.clone();
+        self.run(cmd, metadata, input, output, resource_path, template)
     }
 
     /// Converts a given file with a template to a OpenDocument text or word file. Optionally
@@ -214,33 +259,14 @@ impl<'a> Pandoc {
         metadata: Metadata,
         output: &PathBuf,
         resource_path: Option<&PathBuf>,
-    ) -> Result<(), PandocError<'a>> {
-        let mut cmd = Command::new(self.0.clone());
-        cmd.arg(&input);
+    ) -> Result<Vec<Warning>, PandocError<'a>> {
+        check_path(input.clone(), "input")?;
+        check_path(output.clone(), "output")?;
+        let mut cmd = Command::new(self.executable.clone());
         if let Some(ref reference) = metadata.reference {
             cmd.arg("--reference-doc").arg(reference);
         }
-        if let Some(options) = metadata.pandoc_options {
-            cmd.args(options);
-        }
-        if let Some(_bibliography) = metadata.bibliography {
-            cmd.arg("--citeproc");
-        }
-        if let Some(path) = resource_path {
-            cmd.arg("--resource-path").arg(path);
-        }
-        cmd.arg("-o").arg(&output);
-        match Pandoc::output_to_result(
-            cmd.output(),
-            self.0.clone(),
-            false,
-            String::from(input.to_str().unwrap()),
-            String::from(output.to_str().unwrap()),
-            None,
-        ) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
-        }
+        self.run(cmd, metadata, input, output, resource_path, None)
     }
 
     /// Converts a given file with a template to a Reveal.js presentation. Optionally it's possible
@@ -254,50 +280,182 @@ impl<'a> Pandoc {
         metadata: Metadata,
         output: &PathBuf,
         resource_path: Option<&PathBuf>,
-    ) -> Result<(), PandocError<'a>> {
-        let mut cmd = Command::new(self.0.clone());
-        cmd.arg(&input)
-            .arg("-t")
-            .arg("revealjs")
-            .arg("-s");
+    ) -> Result<Vec<Warning>, PandocError<'a>> {
+        check_path(input.clone(), "input")?;
+        check_path(output.clone(), "output")?;
+        let mut cmd = Command::new(self.executable.clone());
+        cmd.arg("-t").arg("revealjs").arg("-s");
+        Pandoc::add_includes(&mut cmd, &metadata);
+        self.run(cmd, metadata, input, output, resource_path, None)
+    }
+
+    /// Converts the given file into pandoc's JSON AST and returns it together with the warnings
+    /// pandoc reported. Used to apply the filters implemented in Rust before the conversion into
+    /// the output format. The input format given in the pandoc options of the metadata (ex.: `-f
+    /// markdown+hard_line_breaks`) is used to read the file. Pandoc is stopped when it runs longer
+    /// than the timeout of the metadata.
+    pub fn convert_to_json(
+        &self,
+        input: &PathBuf,
+        metadata: &Metadata,
+    ) -> Result<(String, Vec<Warning>), PandocError<'a>> {
+        check_path(input.clone(), "input")?;
+        let mut cmd = Command::new(self.executable.clone());
+        cmd.arg(input);
+        let options = metadata.pandoc_options.as_deref().unwrap_or_default();
+        if let (Some(reader), _) = split_reader(options) {
+            cmd.arg("--from").arg(reader);
+        }
+        cmd.arg("-t").arg("json");
+        self.output_to_result(
+            process::output(&mut cmd, metadata.timeout, &self.cancel),
+            String::from(input.to_str().unwrap()),
+            String::from("json"),
+            None,
+        )
+    }
+
+    /// Adds the options shared by the conversion into a PDF and into LaTeX.
+    fn add_latex_options(cmd: &mut Command, metadata: &Metadata) {
+        cmd.arg("--wrap=preserve");
+        if let Some(ref template) = metadata.template {
+            cmd.arg("--template").arg(template);
+        }
+        Pandoc::add_includes(cmd, metadata);
+    }
+
+    /// Adds the files to be included into the output. Only supported by the formats using a
+    /// pandoc template (PDF and reveal.js).
+    fn add_includes(cmd: &mut Command, metadata: &Metadata) {
+        for file in &metadata.include_in_header {
+            cmd.arg("--include-in-header").arg(file);
+        }
+        for file in &metadata.include_before_body {
+            cmd.arg("--include-before-body").arg(file);
+        }
+    }
+
+    /// Adds the filter pipeline of the header to the command, in the order the entries are given.
+    /// If a bibliography is used and the pipeline doesn't state the position of `citeproc`, the
+    /// citations are processed after all other filters.
+    fn add_filters(&self, cmd: &mut Command, filters: &[PandocFilter], bibliography: bool) {
+        for filter in filters {
+            match filter {
+                PandocFilter::Library(x) => cmd.arg("--lua-filter").arg(x.path()),
+                PandocFilter::Lua(x) => cmd.arg("--lua-filter").arg(x),
+                PandocFilter::Json(x) => cmd.arg("--filter").arg(x),
+                PandocFilter::Citeproc => self.citeproc(cmd),
+            };
+        }
+        if bibliography && !filters.iter().any(|x| matches!(x, PandocFilter::Citeproc)) {
+            self.citeproc(cmd);
+        }
+    }
+
+    /// Adds the input file and the arguments shared by all output formats to the command and
+    /// executes it. The additional pandoc options of the header are added after the options set
+    /// by rsmooth. Returns the warnings pandoc reported while the conversion.
+    fn run(
+        &self,
+        mut cmd: Command,
+        metadata: Metadata,
+        input: &PathBuf,
+        output: &PathBuf,
+        resource_path: Option<&PathBuf>,
+        template: Option<PathBuf>,
+    ) -> Result<Vec<Warning>, PandocError<'a>> {
+        cmd.arg(input);
+        // Input which already went through the filters implemented in Rust is pandoc's JSON AST,
+        // the input format of the pandoc options was already applied while creating it.
+        let json = input.extension().map(|x| x == "json").unwrap_or(false);
+        if json {
+            cmd.arg("--from").arg("json");
+        }
+        if metadata.toc {
+            cmd.arg("--toc");
+        }
+        if let Some(depth) = metadata.toc_depth {
+            cmd.arg(format!("--toc-depth={}", depth));
+        }
+        if metadata.number_sections {
+            cmd.arg("--number-sections");
+        }
+        if let Some(ref style) = metadata.highlight_style {
+            cmd.arg("--highlight-style").arg(style);
+        }
+        if let Some(ref lang) = metadata.lang {
+            cmd.arg("--metadata").arg(format!("lang={}", lang));
+        }
+        for (name, value) in &metadata.variables {
+            cmd.arg("--variable").arg(match value {
+                Some(x) => format!("{}={}", name, x),
+                None => name.clone(),
+            });
+        }
+        for file in &metadata.metadata_files {
+            cmd.arg("--metadata-file").arg(file);
+        }
+        for filter in &metadata.lua_filters {
+            cmd.arg("--lua-filter").arg(filter);
+        }
         if let Some(options) = metadata.pandoc_options {
-            cmd.args(options);
+            match json {
+                true => cmd.args(split_reader(&options).1),
+                false => cmd.args(options),
+            };
+        }
+        for file in &metadata.bibliography {
+            cmd.arg("--bibliography").arg(file);
         }
-        if let Some(_bibliography) = metadata.bibliography {
-            cmd.arg("--citeproc");
+        if let Some(ref csl) = metadata.csl {
+            cmd.arg("--csl").arg(csl);
         }
-        if let Some(path) = resource_path {
-            cmd.arg("--resource-path").arg(path);
+        self.add_filters(&mut cmd, &metadata.filters, !metadata.bibliography.is_empty());
+        let resource_paths: Vec<&PathBuf> = resource_path
+            .into_iter()
+            .chain(metadata.resource_paths.iter())
+            .collect();
+        if !resource_paths.is_empty() {
+            match env::join_paths(resource_paths) {
+                Ok(x) => cmd.arg("--resource-path").arg(x),
+                Err(_) => return Err(PandocError::InvalidResourcePath),
+            };
         }
-        cmd.arg("-o").arg(&output);
-        match Pandoc::output_to_result(
-            cmd.output(),
-            self.0.clone(),
-            false,
+        cmd.arg("-o").arg(output);
+        self.output_to_result(
+            process::output(&mut cmd, metadata.timeout, &self.cancel),
             String::from(input.to_str().unwrap()),
             String::from(output.to_str().unwrap()),
-            None,
-        ) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
-        }
+            template.map(|x| x.to_string_lossy().into_owned()),
+        )
+        .map(|(_, warnings)| warnings)
     }
 
+    /// Adds the citation processing to the command. Since pandoc 2.11 citeproc is built in,
+    /// before it was a separate filter.
+    fn citeproc<'b>(&self, cmd: &'b mut Command) -> &'b mut Command {
+        match self.at_least(&[2, 11]) {
+            true => cmd.arg("--citeproc"),
+            false => cmd.arg("--filter").arg("pandoc-citeproc"),
+        }
+    }
 
-    /// Checks the output of a pandoc call and returns the appropriate result.
+    /// Checks the output of a pandoc call and returns the appropriate result. On success the
+    /// standard output and the warnings pandoc reported are returned. On failure the detected
+    /// version of pandoc accompanies the error.
     fn output_to_result(
-        rsl: io::Result<Output>,
-        pandoc_bin: String,
-        tried_extracting_header: bool,
+        &self,
+        rsl: Result<Output, ProcessError>,
         input: String,
         output: String,
         temlate: Option<String>,
-    ) -> Result<String, PandocError<'a>> {
+    ) -> Result<(String, Vec<Warning>), PandocError<'a>> {
         match rsl {
             Ok(x) => {
                 if x.status.success() {
+                    let warnings = warning::parse(&String::from_utf8_lossy(&x.stderr));
                     match String::from_utf8(x.stdout) {
-                        Ok(x) => Ok(x),
+                        Ok(x) => Ok((x, warnings)),
                         Err(_) => Err(PandocError::StringFromUtf8),
                     }
                 } else {
@@ -306,17 +464,23 @@ impl<'a> Pandoc {
                         output: output,
                         template: temlate,
                         err: String::from_utf8(x.stderr).unwrap(),
-                        tried_extracting_header: tried_extracting_header,
+                        version: self.version.as_ref().map(|x| x.to_string()),
                     }))
                 }
             }
-            Err(e) => {
-                if let ErrorKind::NotFound = e.kind() {
-                    Err(PandocError::NotFound(pandoc_bin))
-                } else {
-                    Err(PandocError::CallFailed(e))
-                }
+            Err(e) => Err(Pandoc::process_error(e, self.executable.clone())),
+        }
+    }
+
+    /// Returns the error for a pandoc call which couldn't be executed or was stopped.
+    fn process_error(err: ProcessError, pandoc_bin: String) -> PandocError<'a> {
+        match err {
+            ProcessError::Io(e) if e.kind() == ErrorKind::NotFound => {
+                PandocError::NotFound(pandoc_bin)
             }
+            ProcessError::Io(e) => PandocError::CallFailed(e),
+            ProcessError::Timeout(x) => PandocError::ExecutionTimeout(x),
+            ProcessError::Cancelled => PandocError::Cancelled,
         }
     }
 }
@@ -328,3 +492,86 @@ fn check_path<'a>(path: PathBuf, purpose: &'a str) -> Result<(), PandocError<'a>
         false => Err(PandocError::RelativePath(path, purpose)),
     }
 }
+
+/// Splits the input format (`-f`, `-r`, `--from` or `--read`) off the given pandoc options.
+/// Returns the input format, the last one if given multiple times, and the remaining options.
+fn split_reader(options: &[String]) -> (Option<String>, Vec<String>) {
+    let mut reader = None;
+    let mut rest = Vec::new();
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        match option.as_str() {
+            "-f" | "-r" | "--from" | "--read" => reader = iter.next().cloned(),
+            x => match ["--from=", "--read=", "-f", "-r"]
+                .iter()
+                .find_map(|y| x.strip_prefix(y))
+            {
+                Some(y) => reader = Some(String::from(y)),
+                None => rest.push(option.clone()),
+            },
+        }
+    }
+    (reader, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pandoc;
+    use crate::filter::{LuaFilter, PandocFilter};
+    use crate::process::CancelHandle;
+    use crate::version::Version;
+
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::sync::Arc;
+
+    #[test]
+    fn filter_order() {
+        let pandoc = Pandoc {
+            executable: String::from("pandoc"),
+            version: Some(Version::new(&[3, 1])),
+            cancel: CancelHandle::new(),
+        };
+        let library = Arc::new(LuaFilter::named("pagebreak").unwrap());
+        let filters = vec![
+            PandocFilter::Json(PathBuf::from("crossref")),
+            PandocFilter::Citeproc,
+            PandocFilter::Library(library.clone()),
+            PandocFilter::Lua(PathBuf::from("a.lua")),
+        ];
+        let mut cmd = Command::new("pandoc");
+        pandoc.add_filters(&mut cmd, &filters, true);
+        let args: Vec<PathBuf> = cmd.get_args().map(PathBuf::from).collect();
+        let expected = vec![
+            PathBuf::from("--filter"),
+            PathBuf::from("crossref"),
+            PathBuf::from("--citeproc"),
+            PathBuf::from("--lua-filter"),
+            library.path(),
+            PathBuf::from("--lua-filter"),
+            PathBuf::from("a.lua"),
+        ];
+        assert_eq!(args, expected);
+
+        // Without an explicit position the citations are processed after all filters.
+        let mut cmd = Command::new("pandoc");
+        pandoc.add_filters(&mut cmd, &filters[3..], true);
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(args, vec!["--lua-filter", "a.lua", "--citeproc"]);
+    }
+
+    #[test]
+    fn split_reader() {
+        let options: Vec<String> = ["-s", "-f", "markdown+hard_line_breaks", "--toc"]
+            .iter()
+            .map(|x| String::from(*x))
+            .collect();
+        let (reader, rest) = super::split_reader(&options);
+        assert_eq!(reader.as_deref(), Some("markdown+hard_line_breaks"));
+        assert_eq!(rest, vec!["-s", "--toc"]);
+        let options = vec![String::from("--from=commonmark"), String::from("--filter=x")];
+        let (reader, rest) = super::split_reader(&options);
+        assert_eq!(reader.as_deref(), Some("commonmark"));
+        assert_eq!(rest, vec!["--filter=x"]);
+    }
+}
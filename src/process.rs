@@ -0,0 +1,166 @@
+/// This module runs the external tools (pandoc and LibreOffice). A run can be limited in time and
+/// cancelled from another thread, in both cases the process and all processes it started (ex.:
+/// the LaTeX engine called by pandoc) are killed.
+use std::io::{Error as IOError, Read};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Interval in which a running process is checked for it's completion, the timeout and
+/// cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Handle to cancel running conversions from another thread (ex.: when the user hits Ctrl-C). All
+/// clones of a handle share the same state. Once cancelled the running external tools are killed,
+/// the conversion stops with an error and the temporary files are removed.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    /// Returns a new handle which isn't cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels all conversions using this handle.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the handle was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Errors occurring while running an external tool.
+pub enum ProcessError {
+    /// The process couldn't be started or observed. Contains the cause.
+    Io(IOError),
+    /// The process didn't finish within the timeout and was killed. Contains the timeout.
+    Timeout(Duration),
+    /// The process was killed as the conversion was cancelled.
+    Cancelled,
+}
+
+/// Executes the command and collects it's output like `Command::output` does. The standard input
+/// of the process is closed, thus a tool asking for input (ex.: LaTeX on an error) fails instead of
+/// waiting forever. The process is killed when it runs longer than the timeout or the handle gets
+/// cancelled.
+pub fn output(
+    cmd: &mut Command,
+    timeout: Option<Duration>,
+    cancel: &CancelHandle,
+) -> Result<Output, ProcessError> {
+    if cancel.is_cancelled() {
+        return Err(ProcessError::Cancelled);
+    }
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    #[cfg(unix)]
+    {
+        // The process gets it's own process group, this way it can be killed together with the
+        // processes it started.
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    let mut child = match cmd.spawn() {
+        Ok(x) => x,
+        Err(e) => return Err(ProcessError::Io(e)),
+    };
+    // The output is read while the process runs as a full pipe would block the process.
+    let stdout = collect(child.stdout.take());
+    let stderr = collect(child.stderr.take());
+
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(x)) => break x,
+            Ok(None) => {}
+            Err(e) => {
+                kill(&mut child);
+                return Err(ProcessError::Io(e));
+            }
+        }
+        if cancel.is_cancelled() {
+            kill(&mut child);
+            return Err(ProcessError::Cancelled);
+        }
+        if let Some(x) = timeout {
+            if start.elapsed() >= x {
+                kill(&mut child);
+                return Err(ProcessError::Timeout(x));
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Reads the given output stream of a process on a separate thread.
+fn collect<R: Read + Send + 'static>(stream: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut rsl = Vec::new();
+        if let Some(mut x) = stream {
+            let _ = x.read_to_end(&mut rsl);
+        }
+        rsl
+    })
+}
+
+/// Kills the process with all processes of it's process group and waits for it to exit.
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CancelHandle, ProcessError};
+
+    use std::process::Command;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn timeout() {
+        let started = Instant::now();
+        let rsl = super::output(
+            Command::new("sleep").arg("10"),
+            Some(Duration::from_millis(200)),
+            &CancelHandle::new(),
+        );
+        assert!(matches!(rsl, Err(ProcessError::Timeout(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn cancel() {
+        let cancel = CancelHandle::new();
+        let handle = cancel.clone();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            handle.cancel();
+        });
+        let started = Instant::now();
+        let rsl = super::output(Command::new("sleep").arg("10"), None, &cancel);
+        canceller.join().unwrap();
+        assert!(matches!(rsl, Err(ProcessError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // A cancelled handle doesn't start new processes.
+        let rsl = super::output(&mut Command::new("true"), None, &cancel);
+        assert!(matches!(rsl, Err(ProcessError::Cancelled)));
+    }
+}
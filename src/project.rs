@@ -0,0 +1,168 @@
+/// This module handles project files describing multiple documents and their shared settings.
+use crate::error::SmoothError;
+use crate::metadata;
+use crate::util::{self, OneOrMany};
+use crate::OutputFormat;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::value::{Map, Value};
+
+/// Names of the project file in the order they're looked up.
+const PROJECT_FILES: [&str; 3] = ["rsmooth.yaml", "rsmooth.yml", "rsmooth.toml"];
+
+/// Content of a project file.
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    /// Header values applied to all documents of the project. The header of a document takes
+    /// priority over these values.
+    defaults: Option<Value>,
+    /// Named sets of header values which can be selected for a build. Merged into the `profiles`
+    /// field of the defaults.
+    profiles: Option<Value>,
+    /// The documents of the project.
+    #[serde(default)]
+    documents: Vec<ManifestDocument>,
+}
+
+/// Describes one document in the project file.
+#[derive(Debug, Deserialize)]
+struct ManifestDocument {
+    /// Path to the markdown file relative to the project file.
+    input: String,
+    /// Output format(s) of the document. If not set the formats given in the header of the
+    /// document are used.
+    format: Option<OneOrMany<String>>,
+    /// Optional output path relative to the project file.
+    output: Option<String>,
+}
+
+/// A document which is part of a project.
+pub struct Document {
+    /// Absolute path to the markdown file.
+    pub input: String,
+    /// Absolute output path, if none is given the output path will be derived from the input.
+    pub output: Option<String>,
+    /// Output formats of the document. If empty the formats of the header are used.
+    pub formats: Vec<OutputFormat>,
+}
+
+/// A project consisting of multiple documents sharing default header values.
+pub struct Project {
+    /// Header values applied to all documents. Relative paths are already resolved relative to
+    /// the project file.
+    pub defaults: Option<Value>,
+    /// The documents of the project.
+    pub documents: Vec<Document>,
+}
+
+impl<'a> Project {
+    /// Reads the project file under the given path. If no path is given the project file will
+    /// be searched in the current working directory.
+    pub fn from(path: Option<&'a str>) -> Result<Self, SmoothError<'a>> {
+        let path = match path {
+            Some(x) => util::normalize_path(x, None)?,
+            None => Project::find()?,
+        };
+        if !path.exists() {
+            return Err(SmoothError::ProjectNotFound(path));
+        }
+        let parent = match path.parent() {
+            Some(x) => x.to_path_buf(),
+            None => return Err(SmoothError::NoParentFolder(path)),
+        };
+        let manifest = Project::read(&path)?;
+
+        let mut defaults = manifest.defaults;
+        if let Some(x) = manifest.profiles {
+            let mut profiles = Map::new();
+            profiles.insert(String::from("profiles"), x);
+            let mut rsl = defaults.unwrap_or_else(|| Value::Object(Map::new()));
+            util::merge_values(&mut rsl, Value::Object(profiles));
+            defaults = Some(rsl);
+        }
+        if let Some(ref mut x) = defaults {
+            metadata::resolve_paths(x, &parent);
+        }
+        let mut documents = Vec::new();
+        for document in manifest.documents {
+            documents.push(Document {
+                input: Project::path_to_string(util::normalize_path(
+                    document.input,
+                    Some(&parent),
+                )?),
+                output: match document.output {
+                    Some(x) => Some(Project::path_to_string(util::normalize_path(
+                        x,
+                        Some(&parent),
+                    )?)),
+                    None => None,
+                },
+                formats: match document.format {
+                    Some(x) => util::parse_formats(x.into_vec())?,
+                    None => vec![],
+                },
+            });
+        }
+        Ok(Self {
+            defaults,
+            documents,
+        })
+    }
+
+    /// Searches the current working directory for a project file.
+    fn find() -> Result<PathBuf, SmoothError<'a>> {
+        let wd = match env::current_dir() {
+            Ok(x) => x,
+            Err(_) => return Err(util::NormalizeError::WdNotFound.into()),
+        };
+        for name in PROJECT_FILES.iter() {
+            let path = wd.join(name);
+            if path.exists() {
+                return Ok(path);
+            }
+        }
+        Err(SmoothError::ProjectNotFound(wd.join(PROJECT_FILES[0])))
+    }
+
+    /// Searches the given folder and its parent folders for a project file. Returns the path to
+    /// the first project file found.
+    pub fn find_upwards(folder: &Path) -> Option<PathBuf> {
+        for dir in folder.ancestors() {
+            for name in PROJECT_FILES.iter() {
+                let path = dir.join(name);
+                if path.exists() {
+                    return Some(path);
+                }
+            }
+        }
+        None
+    }
+
+    /// Parses the project file. TOML is used for files with the `.toml` extension, all other
+    /// files are parsed as YAML.
+    fn read(path: &PathBuf) -> Result<Manifest, SmoothError<'a>> {
+        let content = match fs::read_to_string(path) {
+            Ok(x) => x,
+            Err(e) => return Err(SmoothError::ReadSourceFailed(path.clone(), e)),
+        };
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => match toml::from_str(&content) {
+                Ok(x) => Ok(x),
+                Err(e) => Err(SmoothError::ProjectTomlParseFailure(path.clone(), e)),
+            },
+            _ => match serde_yaml::from_str(&content) {
+                Ok(x) => Ok(x),
+                Err(e) => Err(SmoothError::ProjectYamlParseFailure(path.clone(), e)),
+            },
+        }
+    }
+
+    /// Converts a path into a string.
+    fn path_to_string(path: PathBuf) -> String {
+        path.to_string_lossy().into_owned()
+    }
+}
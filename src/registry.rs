@@ -0,0 +1,188 @@
+/// This module implements the lookup of templates by name. A template stated without a path (ex.:
+/// `template: letter`) is searched in the template folders of the project, the folders of the
+/// `RSMOOTH_TEMPLATE_PATH` environment variable, the named template folders of the user
+/// configuration and the data folder of the user.
+use crate::bundle::Bundle;
+use crate::config::Config;
+use crate::project::Project;
+use crate::OutputFormat;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the environment variable holding additional template folders. Multiple folders are
+/// separated the same way as in the `PATH` variable of the system.
+const TEMPLATE_PATH_ENV: &str = "RSMOOTH_TEMPLATE_PATH";
+
+/// Name of the template folder next to the project file.
+const PROJECT_TEMPLATES: &str = "templates";
+
+/// Path of the template folder relative to the data folder of the user (ex.: `~/.local/share`
+/// on Linux).
+const USER_TEMPLATES: &str = "rsmooth/templates";
+
+/// The folders templates are looked up in, ordered by priority.
+pub struct TemplateRegistry {
+    /// The folders with a description of their origin.
+    folders: Vec<(PathBuf, String)>,
+}
+
+impl TemplateRegistry {
+    /// Returns the registry for a document within the given folder. The `templates` folder next
+    /// to the nearest project file (searched in the folder and all its parent folders) comes
+    /// first, followed by the folders of the `RSMOOTH_TEMPLATE_PATH` environment variable, the
+    /// named template folders of the given user configuration and the `rsmooth/templates` folder
+    /// in the data folder of the user.
+    pub fn new(folder: &Path, config: &Config) -> Self {
+        let mut folders = Vec::new();
+        if let Some(x) = Project::find_upwards(folder) {
+            if let Some(parent) = x.parent() {
+                folders.push((
+                    parent.join(PROJECT_TEMPLATES),
+                    format!("project {}", x.display()),
+                ));
+            }
+        }
+        if let Some(x) = env::var_os(TEMPLATE_PATH_ENV) {
+            for dir in env::split_paths(&x) {
+                folders.push((dir, format!("environment variable {}", TEMPLATE_PATH_ENV)));
+            }
+        }
+        if let Some(ref path) = config.path {
+            for (name, dir) in config.templates.iter() {
+                folders.push((dir.clone(), format!("user config {} (@{})", path.display(), name)));
+            }
+        }
+        if let Some(x) = dirs::data_dir() {
+            folders.push((x.join(USER_TEMPLATES), String::from("user data folder")));
+        }
+        Self { folders }
+    }
+
+    /// Looks up the template with the given name for the given output format. The name is
+    /// tried as it is followed by the name with the file extensions of the format (ex.: `.tex`
+    /// for PDF). Returns the path of the first match.
+    pub fn find(&self, name: &str, format: &OutputFormat) -> Option<PathBuf> {
+        let mut candidates = vec![String::from(name)];
+        for extension in TemplateRegistry::extensions(format) {
+            candidates.push(format!("{}.{}", name, extension));
+        }
+        for (folder, _) in self.folders.iter() {
+            for candidate in candidates.iter() {
+                let path = folder.join(candidate);
+                if path.is_file() {
+                    return Some(path);
+                }
+            }
+        }
+        None
+    }
+
+    /// Looks up the template bundle with the given name. A bundle is a sub folder of one of the
+    /// folders of the registry containing a manifest file. Returns the path to the manifest.
+    pub fn find_bundle(&self, name: &str) -> Option<PathBuf> {
+        self.folders
+            .iter()
+            .find_map(|(folder, _)| Bundle::manifest(&folder.join(name)))
+    }
+
+    /// Returns the folders of the registry with their origin.
+    pub fn folders(&self) -> &[(PathBuf, String)] {
+        &self.folders
+    }
+
+    /// Returns all available templates and template bundles ordered by priority. Every entry
+    /// consists of the name (the file name without extension or the name of the bundle folder),
+    /// the path, the origin of the folder and whether the template is shadowed by a template with
+    /// the same name in a folder of higher priority.
+    pub fn list(&self) -> Vec<(String, PathBuf, String, bool)> {
+        let mut rsl: Vec<(String, PathBuf, String, bool)> = Vec::new();
+        for (folder, source) in self.folders.iter() {
+            let mut files: Vec<PathBuf> = match fs::read_dir(folder) {
+                Ok(x) => x
+                    .filter_map(|y| y.ok())
+                    .map(|y| y.path())
+                    .filter(|y| y.is_file() || Bundle::manifest(y).is_some())
+                    .collect(),
+                Err(_) => continue,
+            };
+            files.sort();
+            for file in files {
+                let name = match file.is_dir() {
+                    true => file.file_name(),
+                    false => file.file_stem(),
+                };
+                let name = match name {
+                    Some(x) => x.to_string_lossy().into_owned(),
+                    None => continue,
+                };
+                let shadowed = rsl
+                    .iter()
+                    .any(|(x, y, _, _)| {
+                        x == &name
+                            && y.is_dir() == file.is_dir()
+                            && y.parent() != Some(folder.as_path())
+                    });
+                rsl.push((name, file, source.clone(), shadowed));
+            }
+        }
+        rsl
+    }
+
+    /// File extensions of templates for the given output format. Only the PDF conversion uses
+    /// a template.
+    fn extensions(format: &OutputFormat) -> &'static [&'static str] {
+        match format {
+            OutputFormat::Pdf => &["tex", "latex"],
+            _ => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TemplateRegistry;
+    use crate::config::Config;
+    use crate::OutputFormat;
+
+    use std::fs;
+
+    #[test]
+    fn lookup_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |x: &str| dir.path().join(x);
+        for folder in ["docs", "templates", "shared"].iter() {
+            fs::create_dir_all(path(folder)).unwrap();
+        }
+        fs::write(path("rsmooth.yaml"), "inputs: [docs]\n").unwrap();
+        for file in ["templates/letter.tex", "shared/letter.tex", "shared/memo.tex"].iter() {
+            fs::write(path(file), "$body$\n").unwrap();
+        }
+        let mut config = Config::default();
+        config.path = Some(path("config.yaml"));
+        config.templates.insert(String::from("shared"), path("shared"));
+
+        let registry = TemplateRegistry::new(&path("docs"), &config);
+        let find = |x: &str, y: OutputFormat| registry.find(x, &y);
+        assert_eq!(find("letter", OutputFormat::Pdf), Some(path("templates/letter.tex")));
+        assert_eq!(find("memo", OutputFormat::Pdf), Some(path("shared/memo.tex")));
+        assert_eq!(find("memo.tex", OutputFormat::Docx), Some(path("shared/memo.tex")));
+        assert_eq!(find("memo", OutputFormat::Docx), None);
+
+        let listed: Vec<(String, bool)> = registry
+            .list()
+            .into_iter()
+            .filter(|(_, x, _, _)| x.starts_with(dir.path()))
+            .map(|(x, _, _, y)| (x, y))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                (String::from("letter"), false),
+                (String::from("letter"), true),
+                (String::from("memo"), false),
+            ]
+        );
+    }
+}
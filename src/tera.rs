@@ -1,11 +1,18 @@
 use crate::error::SmoothError;
+use crate::util;
 
 use std::collections::HashMap;
+use std::fs;
 use std::path::PathBuf;
 
-use serde_json::value::Value;
+use regex::Regex;
+use serde_json::value::{Map, Value};
 use tera::{Context, Tera};
 
+/// Matches the Tera tags which pull in other templates (`include`, `extends` and `import`). The
+/// first group contains the name of the referenced template.
+const INCLUDE_PATTERN: &str = r#"\{%-?\s*(?:include|extends|import)\s+["'`]([^"'`]+)["'`]"#;
+
 /// The template filter applies the Tera template engine on the given string.
 pub struct Template {
     /// Path to the parent folder where the data originates. This is used to make relative paths
@@ -15,6 +22,85 @@ pub struct Template {
     context: HashMap<String, Value>,
 }
 
+/// Reads a data file for the Tera context. The format is determined by the file extension, YAML
+/// (`.yaml`, `.yml`), JSON (`.json`), TOML (`.toml`) and CSV (`.csv`) are supported. A mapping is
+/// returned as it is, all other content (ex.: a list or the rows of a CSV file) is placed under
+/// the name of the file without the extension (ex.: the rows of `staff.csv` are available as
+/// `staff`). Every row of a CSV file is a mapping from the column names to the values.
+pub fn read_context_file<'a>(path: &PathBuf) -> Result<Map<String, Value>, SmoothError<'a>> {
+    if !path.exists() {
+        return Err(SmoothError::ContextFileNotFound(path.clone()));
+    }
+    let content = match fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(e) => return Err(SmoothError::ReadSourceFailed(path.clone(), e)),
+    };
+    let extension = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .map(|x| x.to_lowercase());
+    let rsl: Result<Value, String> = match extension.as_deref() {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+        Some("json") => serde_json::from_str(&content).map_err(|e| e.to_string()),
+        Some("toml") => toml::from_str(&content).map_err(|e| e.to_string()),
+        Some("csv") => read_csv(&content),
+        _ => return Err(SmoothError::UnsupportedContextFile(path.clone())),
+    };
+    match rsl {
+        Ok(Value::Object(x)) => Ok(x),
+        Ok(x) => {
+            let mut rsl = Map::new();
+            let name = path.file_stem().map(|y| y.to_string_lossy().into_owned());
+            rsl.insert(name.unwrap_or_default(), x);
+            Ok(rsl)
+        }
+        Err(e) => Err(SmoothError::ContextFileParseFailure(path.clone(), e)),
+    }
+}
+
+/// Parses a CSV file with a header row into a list of mappings.
+fn read_csv(content: &str) -> Result<Value, String> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let headers = match reader.headers() {
+        Ok(x) => x.clone(),
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut rsl = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(x) => x,
+            Err(e) => return Err(e.to_string()),
+        };
+        let row: Map<String, Value> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(k, v)| (String::from(k), Value::from(v)))
+            .collect();
+        rsl.push(Value::Object(row));
+    }
+    Ok(Value::Array(rsl))
+}
+
+/// Builds the Tera context from the given data files and the inline context of the header. The
+/// files are merged in the given order, later files override earlier ones. The inline context
+/// takes priority over all files.
+pub fn build_context<'a>(
+    files: &[PathBuf],
+    inline: Option<HashMap<String, Value>>,
+) -> Result<HashMap<String, Value>, SmoothError<'a>> {
+    let mut rsl = Value::Object(Map::new());
+    for file in files {
+        util::merge_values(&mut rsl, Value::Object(read_context_file(file)?));
+    }
+    if let Some(x) = inline {
+        util::merge_values(&mut rsl, Value::Object(x.into_iter().collect()));
+    }
+    match rsl {
+        Value::Object(x) => Ok(x.into_iter().collect()),
+        _ => Ok(HashMap::new()),
+    }
+}
+
 impl<'a> Template {
     /// Takes the path of the input markdown file and an optional hash map for the template
     /// context. Returns an instance of the template filter.
@@ -34,6 +120,28 @@ impl<'a> Template {
         })
     }
 
+    /// Returns the paths of all files the given data pulls in using Tera's `include`, `extends`
+    /// and `import` tags. Referenced files are scanned recursively. Files which cannot be read are
+    /// still returned but not scanned any further, Tera will report them while rendering.
+    pub fn dependencies(&self, data: &str) -> Vec<PathBuf> {
+        let re = Regex::new(INCLUDE_PATTERN).unwrap();
+        let mut rsl: Vec<PathBuf> = Vec::new();
+        let mut queue = vec![String::from(data)];
+        while let Some(content) = queue.pop() {
+            for cap in re.captures_iter(&content) {
+                let path = self.wd.join(&cap[1]);
+                if rsl.contains(&path) {
+                    continue;
+                }
+                if let Ok(x) = fs::read_to_string(&path) {
+                    queue.push(x);
+                }
+                rsl.push(path);
+            }
+        }
+        rsl
+    }
+
     pub fn apply(self, data: String) -> Result<String, SmoothError<'a>> {
         let mut pth = self.wd;
         pth.push("**/*.md");
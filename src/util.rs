@@ -1,8 +1,13 @@
+use crate::error::SmoothError;
+use crate::OutputFormat;
+
 use std::env;
 use std::error::Error;
 use std::fmt;
 use std::path::{Path, PathBuf};
 
+use serde::Deserialize;
+use serde_json::value::Value;
 use shellexpand;
 
 /// Errors occurring while the normalization of paths.
@@ -32,6 +37,43 @@ impl fmt::Debug for NormalizeError {
 
 impl Error for NormalizeError {}
 
+/// Value which can either be given as a single element or as a list of elements. Used for header
+/// fields where a list is optional.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    /// A single element.
+    One(T),
+    /// A list of elements.
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    /// Returns the element(s) as a vector.
+    pub fn into_vec(self) -> Vec<T> {
+        match self {
+            OneOrMany::One(x) => vec![x],
+            OneOrMany::Many(x) => x,
+        }
+    }
+}
+
+/// Parses a list of output format names. Returns an error for the first unknown name.
+pub fn parse_formats<'a>(names: Vec<String>) -> Result<Vec<OutputFormat>, SmoothError<'a>> {
+    let mut rsl = Vec::new();
+    for name in names {
+        match name.trim().parse() {
+            Ok(x) => {
+                if !rsl.contains(&x) {
+                    rsl.push(x)
+                }
+            }
+            Err(e) => return Err(SmoothError::UnknownOutputFormat(e)),
+        }
+    }
+    Ok(rsl)
+}
+
 /// Returns the absolute path to a given file. Environment variables (words starting with `$`)
 /// will be take into account. A tilde (`~`) at the beginning of a path will be replaced with the
 /// home directory of the current user. An optional working directory can be specified. Otherwise
@@ -61,10 +103,131 @@ pub fn normalize_path<'a, S: Into<String>>(
     }
 }
 
+/// Splits a string of command line arguments the way a shell does. Arguments are separated by
+/// whitespace, single and double quotes group words containing whitespace and a backslash escapes
+/// the following character (outside of single quotes).
+pub fn split_args(args: &str) -> Vec<String> {
+    let mut rsl = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote: Option<char> = None;
+    let mut chars = args.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\'', None) | ('"', None) => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (x, Some(y)) if x == y => quote = None,
+            ('\\', Some('\'')) => current.push(c),
+            ('\\', _) => {
+                if let Some(x) = chars.next() {
+                    current.push(x);
+                }
+                in_arg = true;
+            }
+            (x, None) if x.is_whitespace() => {
+                if in_arg {
+                    rsl.push(current.clone());
+                    current.clear();
+                    in_arg = false;
+                }
+            }
+            (x, _) => {
+                current.push(x);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        rsl.push(current);
+    }
+    rsl
+}
+
+/// Merges the overlay value into the base value. Maps are merged recursively, thus only the
+/// entries present in the overlay replace the ones of the base. All other values (including
+/// lists) of the overlay replace the value of the base as a whole.
+pub fn merge_values(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(x) => merge_values(x, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Returns whether the given value is a http(s) URL rather than a path.
+pub fn is_url(value: &str) -> bool {
+    let lower = value.trim_start().to_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Computes the 64 bit FNV-1a hash of the given data as hex string. Used to name the files of
+/// the download cache.
+pub fn hash(data: &[u8]) -> String {
+    let mut rsl: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in data {
+        rsl ^= *byte as u64;
+        rsl = rsl.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", rsl)
+}
+
 #[cfg(test)]
 mod tests {
+    use serde_json::json;
     use std::env;
 
+    #[test]
+    fn split_args() {
+        assert_eq!(
+            super::split_args(r#"--toc  -V "title=A B" --lua-filter='my filters/x.lua' a\ b"#),
+            vec!["--toc", "-V", "title=A B", "--lua-filter=my filters/x.lua", "a b"]
+        );
+        assert_eq!(super::split_args(r#"-M empty="""#), vec!["-M", "empty="]);
+        assert!(super::split_args("  ").is_empty());
+    }
+
+    #[test]
+    fn hash() {
+        assert_eq!(super::hash(b""), "cbf29ce484222325");
+        assert_eq!(super::hash(b"a"), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn merge_values() {
+        let mut base = json!({
+            "engine": "xelatex",
+            "tera_context": {"foo": "base", "bar": {"a": 1, "b": 2}},
+            "list": [1, 2, 3],
+        });
+        super::merge_values(
+            &mut base,
+            json!({
+                "template": "doc.tex",
+                "tera_context": {"foo": "overlay", "bar": {"b": 3}},
+                "list": [4],
+            }),
+        );
+        assert_eq!(
+            base,
+            json!({
+                "engine": "xelatex",
+                "template": "doc.tex",
+                "tera_context": {"foo": "overlay", "bar": {"a": 1, "b": 3}},
+                "list": [4],
+            })
+        );
+    }
+
     #[test]
     fn normalize_path() {
         let wd = env::current_dir().unwrap();
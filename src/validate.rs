@@ -0,0 +1,389 @@
+/// This module validates the header values of a document. Misspelled rsmooth fields are reported
+/// with a suggestion, values of the wrong type are reported with the field and where it was set.
+use crate::error::SmoothError;
+use crate::metadata::{Layers, Source};
+
+use serde_json::value::{Map, Value};
+use strsim::damerau_levenshtein;
+
+/// Expected type of the value of a header field.
+#[derive(Clone, Copy)]
+enum FieldType {
+    /// A string.
+    Text,
+    /// A boolean (`true` or `false`).
+    Bool,
+    /// A positive number.
+    Number,
+    /// A positive number of seconds, fractions are allowed.
+    Seconds,
+    /// A string or a list of strings.
+    TextOrList,
+    /// A mapping.
+    Map,
+}
+
+impl FieldType {
+    /// Describes the type for error messages.
+    fn description(&self) -> &'static str {
+        match self {
+            FieldType::Text => "a string",
+            FieldType::Bool => "a boolean (true or false)",
+            FieldType::Number => "a positive number",
+            FieldType::Seconds => "a positive number of seconds",
+            FieldType::TextOrList => "a string or a list of strings",
+            FieldType::Map => "a mapping",
+        }
+    }
+
+    /// Whether the value matches the type.
+    fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (FieldType::Text, Value::String(_)) => true,
+            (FieldType::Bool, Value::Bool(_)) => true,
+            (FieldType::Number, Value::Number(x)) => x.is_u64(),
+            (FieldType::Number, Value::String(x)) => x.trim().parse::<u8>().is_ok(),
+            (FieldType::Seconds, Value::Number(x)) => x.as_f64().map(positive).unwrap_or(false),
+            (FieldType::Seconds, Value::String(x)) if !x.trim().is_empty() => {
+                x.trim().parse::<f64>().map(positive).unwrap_or(false)
+            }
+            (FieldType::TextOrList, Value::String(_)) => true,
+            (FieldType::TextOrList, Value::Array(x)) => x.iter().all(|y| y.is_string()),
+            (FieldType::Map, Value::Object(_)) => true,
+            // Empty values are read as empty strings and treated as not set.
+            (_, Value::String(x)) => x.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// Whether the number is positive and finite (see `Metadata::timeout`).
+fn positive(x: f64) -> bool {
+    x > 0.0 && x.is_finite()
+}
+
+/// All header fields used by rsmooth with their type.
+const FIELDS: [(&str, FieldType); 27] = [
+    ("template", FieldType::Text),
+    ("template_bundle", FieldType::Text),
+    ("reference", FieldType::Text),
+    ("engine", FieldType::Text),
+    ("pandoc_options", FieldType::TextOrList),
+    ("do_tera", FieldType::Bool),
+    ("tera_context", FieldType::Map),
+    ("tera_context_files", FieldType::TextOrList),
+    ("break_description", FieldType::Bool),
+    ("filters", FieldType::TextOrList),
+    ("bibliography", FieldType::TextOrList),
+    ("csl", FieldType::Text),
+    ("format", FieldType::TextOrList),
+    ("pandoc_version", FieldType::Text),
+    ("timeout", FieldType::Seconds),
+    ("formats", FieldType::Map),
+    ("profiles", FieldType::Map),
+    ("extends", FieldType::TextOrList),
+    ("toc", FieldType::Bool),
+    ("toc_depth", FieldType::Number),
+    ("number_sections", FieldType::Bool),
+    ("highlight_style", FieldType::Text),
+    ("lang", FieldType::Text),
+    ("include_in_header", FieldType::TextOrList),
+    ("include_before_body", FieldType::TextOrList),
+    ("variables", FieldType::Map),
+    ("metadata_files", FieldType::TextOrList),
+];
+
+/// Field names of earlier versions which are still accepted, with the current name.
+const DEPRECATED_FIELDS: [(&str, &str); 1] = [("pandoc_optons", "pandoc_options")];
+
+/// Metadata fields and variables of pandoc and it's default templates as documented in the
+/// pandoc manual. They are never reported even if they look similar to a field of rsmooth (ex.:
+/// `css` and `csl` or `references` and `reference`).
+const PANDOC_FIELDS: [&str; 93] = [
+    // Metadata of all formats.
+    "title",
+    "subtitle",
+    "author",
+    "date",
+    "abstract",
+    "abstract-title",
+    "keywords",
+    "subject",
+    "description",
+    "category",
+    "dir",
+    "header-includes",
+    "include-before",
+    "include-after",
+    "toc-title",
+    "toc-depth",
+    "lof",
+    "lot",
+    "numbersections",
+    "secnumdepth",
+    "number-offset",
+    "thanks",
+    // Citations.
+    "references",
+    "nocite",
+    "citation-abbreviations",
+    "link-citations",
+    "link-bibliography",
+    "reference-section-title",
+    "suppress-bibliography",
+    "notes-after-punctuation",
+    "biblatexoptions",
+    "biblio-style",
+    "biblio-title",
+    "natbiboptions",
+    // LaTeX layout and fonts.
+    "documentclass",
+    "classoption",
+    "geometry",
+    "papersize",
+    "pagestyle",
+    "indent",
+    "linestretch",
+    "block-headings",
+    "subparagraph",
+    "fontsize",
+    "fontfamily",
+    "fontfamilyoptions",
+    "fontenc",
+    "mainfont",
+    "mainfontoptions",
+    "sansfont",
+    "sansfontoptions",
+    "monofont",
+    "monofontoptions",
+    "mathfont",
+    "mathfontoptions",
+    "CJKmainfont",
+    "CJKoptions",
+    "microtypeoptions",
+    "csquotes",
+    "hyperrefoptions",
+    "colorlinks",
+    "linkcolor",
+    "filecolor",
+    "citecolor",
+    "urlcolor",
+    "toccolor",
+    "links-as-notes",
+    "margin-left",
+    "margin-right",
+    "margin-top",
+    "margin-bottom",
+    // Beamer and reveal.js.
+    "theme",
+    "colortheme",
+    "fonttheme",
+    "innertheme",
+    "outertheme",
+    "themeoptions",
+    "institute",
+    "logo",
+    "titlegraphic",
+    "aspectratio",
+    "navigation",
+    "section-titles",
+    "beamerarticle",
+    "revealjs-url",
+    "transition",
+    "slideNumber",
+    // HTML.
+    "css",
+    "document-css",
+    "maxwidth",
+    "backgroundcolor",
+    "fontcolor",
+    "monobackgroundcolor",
+];
+
+/// Checks the header values of all layers. Values of the wrong type always result in an error.
+/// Fields which look like misspelled rsmooth fields and deprecated field names are logged as
+/// warnings, in strict mode they result in an error.
+pub fn validate<'a>(layers: &Layers, strict: bool) -> Result<(), SmoothError<'a>> {
+    let mut warnings = Vec::new();
+    for layer in layers.layers() {
+        // The values of a selected profile are already checked within the `profiles` field.
+        if let Source::Profile(_) = layer.source {
+            continue;
+        }
+        if let Value::Object(ref x) = layer.values {
+            check_fields(x, "", &layer.source, &mut warnings)?;
+        }
+    }
+    if warnings.is_empty() {
+        return Ok(());
+    }
+    if strict {
+        return Err(SmoothError::HeaderValidationFailed(warnings));
+    }
+    for warning in warnings {
+        warn!("{}", warning);
+    }
+    Ok(())
+}
+
+/// Checks the fields of a mapping of header values. The prefix states the path of the mapping
+/// within the header (ex.: `formats.pdf.`) and is used for the messages.
+fn check_fields<'a>(
+    fields: &Map<String, Value>,
+    prefix: &str,
+    source: &Source,
+    warnings: &mut Vec<String>,
+) -> Result<(), SmoothError<'a>> {
+    for (key, value) in fields {
+        if let Some((_, typ)) = FIELDS.iter().find(|(x, _)| x == key) {
+            if !typ.matches(value) {
+                return Err(SmoothError::InvalidFieldType(
+                    format!("{}{}", prefix, key),
+                    source.to_string(),
+                    typ.description(),
+                ));
+            }
+            if key == "formats" && !prefix.contains("formats.") {
+                check_formats(value, prefix, source, warnings)?;
+            }
+            if key == "profiles" && prefix.is_empty() {
+                check_profiles(value, source, warnings)?;
+            }
+        } else if let Some((_, current)) = DEPRECATED_FIELDS.iter().find(|(x, _)| x == key) {
+            warnings.push(format!(
+                "{}: field \"{}{}\" is deprecated, use \"{}\" instead",
+                source, prefix, key, current
+            ));
+        } else if let Some(x) = misspelled_field(key) {
+            warnings.push(format!(
+                "{}: unknown field \"{}{}\", did you mean \"{}{}\"?",
+                source, prefix, key, prefix, x
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the blocks of the `formats` field. The keys have to be names of output formats.
+fn check_formats<'a>(
+    formats: &Value,
+    prefix: &str,
+    source: &Source,
+    warnings: &mut Vec<String>,
+) -> Result<(), SmoothError<'a>> {
+    let blocks = match formats {
+        Value::Object(x) => x,
+        _ => return Ok(()),
+    };
+    let names = ["pdf", "odt", "docx", "odtpdf", "reveal"];
+    for (name, block) in blocks {
+        if !names.contains(&name.as_str()) {
+            warnings.push(match suggestion(name, names.iter().copied()) {
+                Some(x) => format!(
+                    "{}: unknown output format \"{}formats.{}\", did you mean \"{}formats.{}\"?",
+                    source, prefix, name, prefix, x
+                ),
+                None => format!(
+                    "{}: unknown output format \"{}formats.{}\"",
+                    source, prefix, name
+                ),
+            });
+        }
+        let path = format!("{}formats.{}", prefix, name);
+        match block {
+            Value::Object(x) => check_fields(x, &format!("{}.", path), source, warnings)?,
+            _ => {
+                return Err(SmoothError::InvalidFieldType(
+                    path,
+                    source.to_string(),
+                    FieldType::Map.description(),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the blocks of the `profiles` field. Every block has to be a mapping of header values.
+fn check_profiles<'a>(
+    profiles: &Value,
+    source: &Source,
+    warnings: &mut Vec<String>,
+) -> Result<(), SmoothError<'a>> {
+    let blocks = match profiles {
+        Value::Object(x) => x,
+        _ => return Ok(()),
+    };
+    for (name, block) in blocks {
+        match block {
+            Value::Object(x) => {
+                check_fields(x, &format!("profiles.{}.", name), source, warnings)?
+            }
+            _ => {
+                return Err(SmoothError::InvalidFieldType(
+                    format!("profiles.{}", name),
+                    source.to_string(),
+                    FieldType::Map.description(),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the rsmooth field the given key is likely a misspelling of. Metadata fields and
+/// variables of pandoc are never reported.
+fn misspelled_field(key: &str) -> Option<&'static str> {
+    if PANDOC_FIELDS.contains(&key) {
+        return None;
+    }
+    suggestion(key, FIELDS.iter().map(|(x, _)| *x))
+}
+
+/// Returns the name most similar to the given key if it's likely the key is a misspelling of
+/// it. Case, dashes and spaces are ignored, otherwise the names may differ by one edit (for short
+/// names) or two edits.
+fn suggestion<'b, I: Iterator<Item = &'b str>>(key: &str, names: I) -> Option<&'b str> {
+    let normalized = key.to_lowercase().replace(&['-', ' '][..], "_");
+    names
+        .map(|x| (x, damerau_levenshtein(&normalized, x)))
+        .filter(|(x, distance)| match x.len() {
+            0..=4 => *distance <= 1,
+            _ => *distance <= 2,
+        })
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(x, _)| x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FieldType;
+
+    use serde_json::json;
+
+    #[test]
+    fn suggestion() {
+        assert_eq!(super::misspelled_field("tempalte"), Some("template"));
+        assert_eq!(super::misspelled_field("tera-context"), Some("tera_context"));
+        assert_eq!(super::misspelled_field("Number_Section"), Some("number_sections"));
+        assert_eq!(super::misspelled_field("title"), None);
+        assert_eq!(super::misspelled_field("author"), None);
+        // Pandoc's own metadata and variables close to rsmooth fields.
+        for key in ["references", "numbersections", "lof", "lot", "secnumdepth", "css"] {
+            assert_eq!(super::misspelled_field(key), None);
+        }
+    }
+
+    #[test]
+    fn seconds() {
+        assert!(FieldType::Seconds.matches(&json!(300)));
+        assert!(FieldType::Seconds.matches(&json!(2.5)));
+        assert!(FieldType::Seconds.matches(&json!("300")));
+        assert!(FieldType::Seconds.matches(&json!("2.5")));
+        assert!(FieldType::Seconds.matches(&json!("")));
+        assert!(!FieldType::Seconds.matches(&json!(0)));
+        assert!(!FieldType::Seconds.matches(&json!("-1")));
+        assert!(!FieldType::Seconds.matches(&json!("inf")));
+        assert!(!FieldType::Seconds.matches(&json!("soon")));
+    }
+}
@@ -0,0 +1,130 @@
+/// This module handles version numbers (ex.: of pandoc) and requirements on them as stated in
+/// the `pandoc_version` header field (ex.: `">=2.11, <4"`).
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// A version number consisting of numeric components (ex.: `2.19.2`).
+#[derive(Debug, Clone)]
+pub struct Version(Vec<u32>);
+
+impl Version {
+    /// Returns the version with the given components.
+    pub fn new(components: &[u32]) -> Self {
+        Self(components.to_vec())
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    /// Parses a version number like `3.1.2`. Leading and trailing whitespace is ignored.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let components: Result<Vec<u32>, _> = value.trim().split('.').map(|x| x.parse()).collect();
+        match components {
+            Ok(x) if !x.is_empty() => Ok(Self(x)),
+            _ => Err(String::from(value)),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let components: Vec<String> = self.0.iter().map(|x| x.to_string()).collect();
+        write!(f, "{}", components.join("."))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    /// Compares the versions component by component, missing components count as zero (thus
+    /// `3.1` equals `3.1.0`).
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.0.len().max(other.0.len());
+        for i in 0..len {
+            let a = self.0.get(i).unwrap_or(&0);
+            let b = other.0.get(i).unwrap_or(&0);
+            match a.cmp(b) {
+                Ordering::Equal => continue,
+                x => return x,
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+/// A requirement on a version consisting of one or more comparisons separated by commas (ex.:
+/// `>=2.11, <4`). The operators `>=`, `>`, `<=`, `<` and `=` are supported, a version without
+/// operator is treated as minimal version.
+#[derive(Debug, Clone)]
+pub struct Requirement(Vec<(Ordering, bool, Version)>);
+
+impl Requirement {
+    /// Returns whether the given version fulfills all comparisons of the requirement.
+    pub fn matches(&self, version: &Version) -> bool {
+        self.0.iter().all(|(ordering, or_equal, required)| {
+            let rsl = version.cmp(required);
+            rsl == *ordering || (*or_equal && rsl == Ordering::Equal)
+        })
+    }
+}
+
+impl FromStr for Requirement {
+    type Err = String;
+
+    /// Parses a requirement like `>=3.1` or `>=2.11, <4`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut rsl = Vec::new();
+        for part in value.split(',') {
+            let part = part.trim();
+            let (ordering, or_equal, version) = if let Some(x) = part.strip_prefix(">=") {
+                (Ordering::Greater, true, x)
+            } else if let Some(x) = part.strip_prefix("<=") {
+                (Ordering::Less, true, x)
+            } else if let Some(x) = part.strip_prefix('>') {
+                (Ordering::Greater, false, x)
+            } else if let Some(x) = part.strip_prefix('<') {
+                (Ordering::Less, false, x)
+            } else if let Some(x) = part.strip_prefix('=') {
+                (Ordering::Equal, true, x.trim_start_matches('='))
+            } else {
+                (Ordering::Greater, true, part)
+            };
+            rsl.push((ordering, or_equal, version.parse().map_err(|_| String::from(value))?));
+        }
+        Ok(Self(rsl))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Requirement, Version};
+
+    #[test]
+    fn requirement() {
+        let version: Version = "2.19.2".parse().unwrap();
+        let matches = |x: &str| x.parse::<Requirement>().unwrap().matches(&version);
+        assert!(matches(">=2.11"));
+        assert!(matches("2.19"));
+        assert!(matches(">=2.11, <3"));
+        assert!(matches("=2.19.2"));
+        assert!(!matches(">=3.1"));
+        assert!(!matches("<2.19.2"));
+        assert!(!matches("==2.19"));
+        assert!("3.x".parse::<Requirement>().is_err());
+        assert_eq!(version, Version::new(&[2, 19, 2]));
+    }
+}
@@ -0,0 +1,162 @@
+/// This module parses the warnings pandoc (and the LaTeX engine run by pandoc) prints while a
+/// conversion succeeds (ex.: a citation which isn't in the bibliography). These warnings are
+/// easily missed, thus rsmooth shows them after the build and can fail on them.
+use std::fmt;
+
+use regex::Regex;
+
+/// Matches the position within the input pandoc states in some warnings (ex.: `at line 12 column
+/// 3`). The groups contain the line and the optional column.
+const LOCATION_PATTERN: &str = r"line (\d+)(?:,? column (\d+))?";
+
+/// Marker of the LaTeX warnings.
+const LATEX_WARNING: &str = "LaTeX Warning:";
+
+/// The category of a warning.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarningKind {
+    /// A citation wasn't found in the bibliography.
+    Citation,
+    /// An image or other resource couldn't be loaded.
+    Resource,
+    /// A link or cross reference couldn't be resolved.
+    Reference,
+    /// A character isn't available in the font.
+    Font,
+    /// All other warnings.
+    Other,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            WarningKind::Citation => "citation",
+            WarningKind::Resource => "resource",
+            WarningKind::Reference => "reference",
+            WarningKind::Font => "font",
+            WarningKind::Other => "warning",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A warning reported while a conversion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    /// Category of the warning.
+    pub kind: WarningKind,
+    /// The message as stated by pandoc or LaTeX.
+    pub message: String,
+    /// Line and optional column within the input of pandoc, if stated.
+    pub location: Option<(usize, Option<usize>)>,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+impl Warning {
+    /// Returns the warning for the given message, the kind is determined from the message. The
+    /// location is only taken from the warnings of pandoc as the lines stated by LaTeX refer to
+    /// the intermediate LaTeX file.
+    fn new(message: String, latex: bool, location: &Regex) -> Self {
+        let lower = message.to_lowercase();
+        let kind = if lower.starts_with("citeproc") || lower.contains("citation") {
+            WarningKind::Citation
+        } else if lower.contains("could not fetch")
+            || lower.contains("could not find image")
+            || lower.contains("could not find media")
+            || lower.contains("could not convert image")
+        {
+            WarningKind::Resource
+        } else if lower.contains("reference not found")
+            || lower.contains("undefined")
+            || lower.contains("duplicate")
+        {
+            WarningKind::Reference
+        } else if lower.contains("missing character") {
+            WarningKind::Font
+        } else {
+            WarningKind::Other
+        };
+        let location = match latex {
+            true => None,
+            false => location.captures(&message).and_then(|x| {
+                let line = x[1].parse().ok()?;
+                Some((line, x.get(2).and_then(|y| y.as_str().parse().ok())))
+            }),
+        };
+        Self {
+            kind,
+            message,
+            location,
+        }
+    }
+}
+
+/// Extracts the warnings from the standard error output of pandoc. Pandoc prefixes it's warnings
+/// with `[WARNING]`, LaTeX with `LaTeX Warning:`. Indented lines following a warning continue
+/// it's message.
+pub fn parse(stderr: &str) -> Vec<Warning> {
+    // The messages and whether they originate from LaTeX.
+    let mut messages: Vec<(String, bool)> = Vec::new();
+    let mut continued = false;
+    for line in stderr.lines() {
+        if let Some(x) = line.strip_prefix("[WARNING]") {
+            messages.push((String::from(x.trim()), false));
+        } else if let Some(x) = line.find(LATEX_WARNING) {
+            messages.push((String::from(line[x + LATEX_WARNING.len()..].trim()), true));
+        } else {
+            match messages.last_mut() {
+                Some((x, _)) if continued && line.starts_with(char::is_whitespace) => {
+                    x.push(' ');
+                    x.push_str(line.trim());
+                }
+                _ => continued = false,
+            }
+            continue;
+        }
+        continued = true;
+    }
+    let location = Regex::new(LOCATION_PATTERN).unwrap();
+    messages
+        .into_iter()
+        .map(|(message, latex)| Warning::new(message, latex, &location))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WarningKind;
+
+    #[test]
+    fn parse() {
+        let stderr = "[WARNING] Citeproc: citation smith2020 not found\n\
+                      [WARNING] Could not fetch resource 'img/logo.png': replacing image with\n  \
+                      description\n\
+                      [INFO] Running pdflatex\n  \
+                      not a warning\n\
+                      [WARNING] Reference not found for 'Link' at line 12 column 3\n\
+                      LaTeX Warning: Reference `fig:a' on page 1 undefined on input line 80.\n";
+        let warnings = super::parse(stderr);
+        let kinds: Vec<WarningKind> = warnings.iter().map(|x| x.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                WarningKind::Citation,
+                WarningKind::Resource,
+                WarningKind::Reference,
+                WarningKind::Reference,
+            ]
+        );
+        assert_eq!(
+            warnings[1].message,
+            "Could not fetch resource 'img/logo.png': replacing image with description"
+        );
+        assert_eq!(warnings[0].location, None);
+        assert_eq!(warnings[2].location, Some((12, Some(3))));
+        assert_eq!(warnings[3].location, None);
+    }
+}
@@ -0,0 +1,180 @@
+/// This module implements the watch mode which converts a document every time it or one of it's
+/// dependencies changes.
+use crate::error::SmoothError;
+use crate::file::File;
+use crate::process::CancelHandle;
+use crate::util;
+use crate::{Options, OutputFormat};
+
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+
+/// Time span in which multiple events for the same file are merged into one. Also used as the
+/// quiet period after the last event of a burst of changes before the document gets rebuild.
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
+
+/// Interval in which the watch checks whether it was cancelled while waiting for changes.
+const CANCEL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Watches a markdown file and all the files it depends upon and rebuilds the document on
+/// changes.
+pub struct Watch<'a> {
+    /// Path to the markdown file as given by the user.
+    path: &'a str,
+    /// Optional destination path for the output file.
+    output: Option<&'a str>,
+    /// Desired formats of the output file.
+    formats: Vec<OutputFormat>,
+    /// Additional settings applied to every build.
+    options: Options,
+}
+
+impl<'a> Watch<'a> {
+    /// Returns a new watch instance for the given document. The parameters are the same as for
+    /// a single conversion.
+    pub fn new(
+        path: &'a str,
+        output: Option<&'a str>,
+        formats: Vec<OutputFormat>,
+        options: Options,
+    ) -> Self {
+        Self {
+            path,
+            output,
+            formats,
+            options,
+        }
+    }
+
+    /// Builds the document and then waits for changes on the files it depends upon. Every change
+    /// triggers a rebuild. As the set of dependencies can change with every edit (ex.: a new
+    /// include was added) the dependencies are determined anew before every build. The files
+    /// are watched before the build starts, thus changes made while building trigger the next
+    /// build. Returns only when the file system watcher fails or the watch is cancelled (see
+    /// `Options::cancel`).
+    pub fn run(self) -> Result<(), SmoothError<'a>> {
+        loop {
+            let (file, dependencies) = self.dependencies();
+            let (tx, rx) = channel();
+            let mut watcher = match watcher(tx, DEBOUNCE_DELAY) {
+                Ok(x) => x,
+                Err(e) => return Err(SmoothError::Watch(e)),
+            };
+            // Folders are watched instead of the files themselves as many editors save a file
+            // by replacing it, which would silently end a watch on the file. For a missing file
+            // the closest existing folder is watched, creating the missing folder triggers a
+            // rebuild which then watches the new folder.
+            let mut folders: Vec<PathBuf> = Vec::new();
+            for file in &dependencies {
+                if let Some(x) = file.ancestors().skip(1).find(|x| x.is_dir()) {
+                    if !folders.iter().any(|y| y == x) {
+                        folders.push(x.to_path_buf());
+                    }
+                }
+            }
+            for folder in &folders {
+                if let Err(e) = watcher.watch(folder, RecursiveMode::NonRecursive) {
+                    return Err(SmoothError::Watch(e));
+                }
+            }
+
+            if let Some(x) = file {
+                match x.convert() {
+                    Ok(_) => info!("converted {}", self.path),
+                    Err(e) => error!("{}", e),
+                }
+            }
+            if self.options.cancel.is_cancelled() {
+                return Err(SmoothError::Cancelled);
+            }
+            info!("watching {} file(s) for changes", dependencies.len());
+            Watch::wait_for_change(&rx, &dependencies, &self.options.cancel)?;
+        }
+    }
+
+    /// Returns the document to build and the files it depends on. Errors are reported but do not
+    /// stop the watch. If the dependencies can't be determined (ex.: because the template is
+    /// missing) the input file and the files referenced in it's front matter are watched.
+    fn dependencies(&self) -> (Option<File>, Vec<PathBuf>) {
+        let file = match File::new(
+            self.path,
+            self.output,
+            self.formats.clone(),
+            self.options.clone(),
+        ) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("{}", e);
+                return match util::normalize_path(self.path, None) {
+                    Ok(x) => (None, vec![x]),
+                    Err(_) => (None, vec![]),
+                };
+            }
+        };
+        let dependencies = match file.dependencies() {
+            Ok(x) => x,
+            Err(e) => {
+                debug!("couldn't determine dependencies {}", e);
+                file.referenced_files()
+            }
+        };
+        (Some(file), dependencies)
+    }
+
+    /// Blocks until one of the given files changes. All further events arriving within the
+    /// debounce delay are consumed as well, thus a burst of saves only results in one rebuild.
+    /// Returns an error when the given handle gets cancelled while waiting.
+    fn wait_for_change(
+        rx: &Receiver<DebouncedEvent>,
+        dependencies: &[PathBuf],
+        cancel: &CancelHandle,
+    ) -> Result<(), SmoothError<'a>> {
+        loop {
+            match rx.recv_timeout(CANCEL_INTERVAL) {
+                Ok(event) => {
+                    if Watch::is_relevant(event, dependencies)? {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) if cancel.is_cancelled() => {
+                    return Err(SmoothError::Cancelled)
+                }
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+        loop {
+            match rx.recv_timeout(DEBOUNCE_DELAY) {
+                Ok(DebouncedEvent::Error(e, _)) => return Err(SmoothError::Watch(e)),
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                    return Ok(())
+                }
+            }
+        }
+    }
+
+    /// States whether a file system event concerns one of the given files or a missing folder
+    /// containing one of them.
+    fn is_relevant(
+        event: DebouncedEvent,
+        dependencies: &[PathBuf],
+    ) -> Result<bool, SmoothError<'a>> {
+        let paths = match event {
+            DebouncedEvent::Create(x)
+            | DebouncedEvent::Write(x)
+            | DebouncedEvent::Chmod(x)
+            | DebouncedEvent::Remove(x) => vec![x],
+            DebouncedEvent::Rename(x, y) => vec![x, y],
+            DebouncedEvent::Rescan => return Ok(true),
+            DebouncedEvent::Error(e, _) => return Err(SmoothError::Watch(e)),
+            DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => vec![],
+        };
+        Ok(paths
+            .iter()
+            .any(|x| dependencies.iter().any(|y| y.starts_with(x))))
+    }
+}